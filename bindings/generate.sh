@@ -0,0 +1,19 @@
+#!/bin/sh
+# Builds the library as a shared and a static library and generates its
+# Kotlin, Swift and Python bindings into bindings/<language>. The Python
+# bindings are ready to use with the library copied next to them. Other builds
+# of the crate only produce the Rust library.
+set -e
+cd "$(dirname "$0")/.."
+
+case "$(uname)" in
+    Darwin) lib=libhub_recovery.dylib ;;
+    *) lib=libhub_recovery.so ;;
+esac
+
+cargo rustc --release --lib --features uniffi --crate-type cdylib,staticlib
+for language in kotlin swift python; do
+    cargo run --release --features uniffi-cli --bin uniffi-bindgen -- generate \
+        --library "target/release/$lib" --language "$language" --out-dir "bindings/$language"
+done
+cp "target/release/$lib" bindings/python/
@@ -0,0 +1,127 @@
+"""Tests of the Python bindings. Generate the bindings into this directory
+first, see bindings/README.md."""
+
+import json
+import os
+import tempfile
+import unittest
+
+from hub_recovery import (
+    RecoveryError,
+    RecoverySession,
+    SessionConfig,
+    derive_node_id,
+    inspect_backup,
+)
+
+SEED = "limit reward expect search tissue call visa fit thank cream brave jump"
+NODE_ID = "037e702144c4fa485d42f0f69864e943605823763866cf4bf619d2d2cf2eda420b"
+# Encrypted backup of the node without channels.
+ENCRYPTED_BACKUP = "3fd21f9a393d8345ddbdd449-ba05c3dbafdfb7eea574373b7763d0c81c599b2cd1735e59a1c5571379498f4da8fe834c3403824ab02b61005abc1f563c638f425c65420e82941efe94794555c8b145a0603733ee115277f860011e6a17fd8c22f1d73a096ff7275582aac19b430940b40a2559c7ff59a063305290ef7c9ba46f9de17b0ddbac9030b0"
+OTHER_SEED = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about"
+PEER_ID = "02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619"
+
+
+def backup(*channel_ids):
+    return json.dumps({
+        "node_id": NODE_ID,
+        "channels": [
+            {
+                "channel_id": channel_id,
+                "peer_id": PEER_ID,
+                "peer_socket_address": "127.0.0.1:9735",
+                "funding_tx_id": channel_id,
+            }
+            for channel_id in channel_ids
+        ],
+        "monitors": [],
+    })
+
+
+def session_config(data_dir):
+    return SessionConfig(
+        data_dir=data_dir,
+        network="regtest",
+        esplora_servers=["http://127.0.0.1:3002"],
+        electrum_server=None,
+        proxy=None,
+        peer_addresses={},
+        sync_interval_min_secs=1,
+        sync_interval_max_secs=10,
+    )
+
+
+class TestBackup(unittest.TestCase):
+    def test_derive_node_id(self):
+        self.assertEqual(derive_node_id(SEED), NODE_ID)
+
+    def test_invalid_seed(self):
+        with self.assertRaises(RecoveryError.WrongSeed):
+            derive_node_id("not a seed phrase")
+
+    def test_inspect_encrypted_backup(self):
+        info = inspect_backup(ENCRYPTED_BACKUP, SEED)
+        self.assertEqual(info.node_id, NODE_ID)
+        self.assertEqual(info.channels, [])
+        self.assertEqual(info.monitors, [])
+
+    def test_inspect_plain_backup(self):
+        info = inspect_backup(backup("aa", "bb"), SEED)
+        self.assertEqual([c.channel_id for c in info.channels], ["aa", "bb"])
+        self.assertEqual(info.channels[0].peer_id, PEER_ID)
+
+    def test_inspect_with_wrong_seed(self):
+        with self.assertRaises(RecoveryError.WrongSeed):
+            inspect_backup(ENCRYPTED_BACKUP, OTHER_SEED)
+        with self.assertRaises(RecoveryError.WrongSeed):
+            inspect_backup(backup("aa"), OTHER_SEED)
+
+    def test_inspect_truncated_backup(self):
+        with self.assertRaises(RecoveryError.BadBackup):
+            inspect_backup(ENCRYPTED_BACKUP[:20], SEED)
+
+
+class TestSession(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.addCleanup(self.dir.cleanup)
+
+    def test_backup_without_channels(self):
+        with self.assertRaises(RecoveryError.BadBackup):
+            RecoverySession(session_config(self.dir.name), SEED, backup())
+
+    def test_resume(self):
+        session = RecoverySession(session_config(self.dir.name), SEED, backup("aa"))
+        self.assertFalse(session.is_resumed())
+        self.assertTrue(os.path.isdir(os.path.join(self.dir.name, "recoveries", NODE_ID)))
+        del session
+
+        session = RecoverySession(session_config(self.dir.name), SEED, backup("aa"))
+        self.assertTrue(session.is_resumed())
+
+    def test_data_dir_in_use(self):
+        session = RecoverySession(session_config(self.dir.name), SEED, backup("aa"))
+        with self.assertRaises(RecoveryError.Failed):
+            RecoverySession(session_config(self.dir.name), SEED, backup("aa"))
+        del session
+
+    def test_changed_backup(self):
+        session = RecoverySession(session_config(self.dir.name), SEED, backup("aa"))
+        del session
+        with self.assertRaises(RecoveryError.BackupChanged):
+            RecoverySession(session_config(self.dir.name), SEED, backup("bb"))
+
+    def test_poll_after_stop(self):
+        session = RecoverySession(session_config(self.dir.name), SEED, backup("aa"))
+        session.stop()
+        self.assertIsNone(session.poll())
+
+    def test_invalid_config(self):
+        config = session_config(self.dir.name)
+        config.esplora_servers = []
+        with self.assertRaises(RecoveryError.Failed):
+            RecoverySession(config, SEED, backup("aa"))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,16 @@
+//! Parses plain backups and walks the parsed channels and monitors.
+
+#![no_main]
+
+use hub_recovery::scb;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(scb) = scb::parse_scb(data) {
+        let summary = scb.summary();
+        assert_eq!(summary.channels.len(), scb.channels.len());
+        for monitor in &scb.monitors {
+            let _ = scb.channels_of_monitor(&monitor.key);
+        }
+    }
+});
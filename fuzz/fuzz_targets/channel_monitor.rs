@@ -0,0 +1,15 @@
+//! Reads channel monitors the way the recovery checks the ones it restores,
+//! which is how the node reads them when it starts.
+
+#![no_main]
+
+use hub_recovery::scb;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(update_id) = scb::read_monitor(data) {
+        // Outdated backups are detected by the update ID read from the raw
+        // bytes, which must agree with the decoded monitor.
+        assert_eq!(scb::monitor_update_id(data), Some(update_id));
+    }
+});
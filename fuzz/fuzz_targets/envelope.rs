@@ -0,0 +1,13 @@
+//! Splits encrypted backups into nonce and ciphertext, as received from users
+//! and possibly mangled on the way.
+
+#![no_main]
+
+use hub_recovery::scb;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok((nonce, _ciphertext)) = scb::parse_envelope(data) {
+        assert_eq!(nonce.len(), 12);
+    }
+});
@@ -0,0 +1,16 @@
+//! Decodes channel monitors from backups and reads their update IDs.
+
+#![no_main]
+
+use hub_recovery::scb::{self, EncodedChannelMonitorBackup};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = scb::monitor_update_id(data);
+
+    if let Ok(monitor) = serde_json::from_slice::<EncodedChannelMonitorBackup>(data) {
+        let summary = monitor.summary();
+        assert_eq!(summary.size, monitor.value.len() as u64);
+        assert_eq!(summary.update_id, scb::monitor_update_id(&monitor.value));
+    }
+});
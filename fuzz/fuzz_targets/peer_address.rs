@@ -0,0 +1,10 @@
+//! Parses peer addresses from backups and from the command line.
+
+#![no_main]
+
+use hub_recovery::scb;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    let _ = scb::parse_peer_address(data);
+});
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use ldk_node::Event;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::metrics::Metrics;
+use crate::progress::{self, PeerStatus, Snapshot};
+
+/// Number of events kept for the `/events` endpoint.
+const MAX_EVENTS: usize = 200;
+
+#[derive(Serialize)]
+struct EventRecord {
+    timestamp: u64,
+    event: String,
+}
+
+#[derive(Default)]
+struct ApiData {
+    snapshot: Option<Snapshot>,
+    peers: Vec<PeerStatus>,
+    events: VecDeque<EventRecord>,
+}
+
+/// Read-only HTTP/JSON view of a running recovery, served from the data the
+/// main loop collects.
+pub struct ApiServer {
+    data: Arc<Mutex<ApiData>>,
+    metrics: Arc<Metrics>,
+}
+
+impl ApiServer {
+    /// Starts serving on the given address. The sync is reported as unhealthy
+    /// once the last successful sync is older than `stale_after`.
+    pub fn start(addr: SocketAddr, stale_after: Duration, metrics: Arc<Metrics>) -> Result<Self> {
+        let server =
+            Server::http(addr).map_err(|e| anyhow!("failed to listen on {}: {}", addr, e))?;
+        if !addr.ip().is_loopback() {
+            warn!(
+                "status API listens on non-loopback address {}; it is not authenticated",
+                addr
+            );
+        }
+        info!("status API listening on {}", addr);
+
+        let data = Arc::new(Mutex::new(ApiData::default()));
+        let server_data = Arc::clone(&data);
+        let server_metrics = Arc::clone(&metrics);
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let (status, content_type, body) = match path(request.url()) {
+                    "/metrics" if *request.method() == Method::Get => {
+                        (200, "text/plain; version=0.0.4", server_metrics.encode())
+                    }
+                    _ => {
+                        let (status, body) = handle(&request, &server_data, stale_after);
+                        (status, "application/json", body.to_string())
+                    }
+                };
+                let response = Response::from_string(body)
+                    .with_status_code(status)
+                    .with_header(
+                        Header::from_bytes("Content-Type", content_type).expect("valid header"),
+                    );
+                if let Err(e) = request.respond(response) {
+                    error!("failed to respond to API request: {:?}", e);
+                }
+            }
+        });
+
+        Ok(Self { data, metrics })
+    }
+
+    pub fn update(&self, snapshot: &Snapshot, peers: Vec<PeerStatus>) {
+        self.metrics.observe(snapshot);
+        let mut data = self.data.lock().unwrap();
+        data.snapshot = Some(snapshot.clone());
+        data.peers = peers;
+    }
+
+    pub fn record_event(&self, event: &Event) {
+        self.metrics.record_event(event);
+        let mut data = self.data.lock().unwrap();
+        if data.events.len() == MAX_EVENTS {
+            data.events.pop_front();
+        }
+        data.events.push_back(EventRecord {
+            timestamp: progress::now(),
+            event: progress::describe_event(event),
+        });
+    }
+}
+
+/// The path of a request URL, without the query string.
+fn path(url: &str) -> &str {
+    url.split('?').next().unwrap_or_default()
+}
+
+fn handle(request: &Request, data: &Mutex<ApiData>, stale_after: Duration) -> (u16, Value) {
+    if *request.method() != Method::Get {
+        return (405, json!({ "error": "method not allowed" }));
+    }
+
+    let data = data.lock().unwrap();
+    let path = path(request.url());
+    match (path, &data.snapshot) {
+        ("/events", _) => (200, json!(data.events)),
+        ("/peers", _) => (200, json!(data.peers)),
+        (_, None) if matches!(path, "/status" | "/health") => {
+            (503, json!({ "error": "recovery is starting" }))
+        }
+        ("/status", Some(s)) => (
+            200,
+            json!({
+                "tip_height": s.tip_height,
+                "chain_source": s.chain_source,
+                "balances": {
+                    "spendable": s.report.spendable,
+                    "total": s.report.total,
+                    "reserved": s.report.reserved,
+                    "claimable": s.report.claimable_total(),
+                    "pending_sweep": s.report.pending_sweep_total(),
+                },
+                "channels": s.channels,
+                "claimable": s.report.claimable,
+                "pending_sweep": s.report.pending_sweep,
+            }),
+        ),
+        ("/health", Some(s)) => {
+            let age = s.last_sync.map(|t| progress::now().saturating_sub(t));
+            let healthy = age.is_some_and(|a| a <= stale_after.as_secs());
+            (
+                if healthy { 200 } else { 503 },
+                json!({
+                    "healthy": healthy,
+                    "tip_height": s.tip_height,
+                    "last_sync": s.last_sync,
+                    "last_sync_age_secs": age,
+                    "chain_source": s.chain_source,
+                }),
+            )
+        }
+        _ => (404, json!({ "error": "not found" })),
+    }
+}
@@ -1,74 +1,141 @@
 use std::collections::{HashMap, HashSet};
-use std::ops::Not;
 
 use ldk_node::lightning::ln::types::ChannelId;
 use ldk_node::{LightningBalance, Node, PendingSweepBalance};
-use log::info;
+use serde::Serialize;
 
 use crate::scb::ChannelBackup;
 
-fn get_ln_balance_channel_amount(balance: &LightningBalance) -> (ChannelId, u64) {
+/// Number of confirmations after which LDK considers a sweep final.
+const ANTI_REORG_DELAY: u32 = 6;
+
+/// Funds attributed to a single channel.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ChannelAmount {
+    pub channel_id: Option<String>,
+    pub peer_id: Option<String>,
+    pub funding_tx: Option<String>,
+    pub amount: u64,
+    /// Block height at which the funds are expected to become available.
+    pub unlock_height: Option<u32>,
+    /// The funds wait for a transaction to confirm.
+    pub awaiting_confirmation: bool,
+    /// The funds are only claimable once the channel is closed, i.e. its
+    /// closing transaction has not confirmed yet.
+    pub awaiting_close: bool,
+}
+
+/// Snapshot of the node's balances.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct BalanceReport {
+    pub spendable: u64,
+    pub total: u64,
+    pub reserved: u64,
+    pub claimable: Vec<ChannelAmount>,
+    pub pending_sweep: Vec<ChannelAmount>,
+}
+
+impl BalanceReport {
+    pub fn claimable_total(&self) -> u64 {
+        self.claimable.iter().map(|c| c.amount).sum()
+    }
+
+    pub fn pending_sweep_total(&self) -> u64 {
+        self.pending_sweep.iter().map(|c| c.amount).sum()
+    }
+
+    /// Funds from channel closures that are not yet in the on-chain wallet.
+    pub fn pending_total(&self) -> u64 {
+        self.claimable_total() + self.pending_sweep_total()
+    }
+
+    /// Whether some funds are expected to confirm or unlock within the next
+    /// block.
+    pub fn expects_confirmation(&self, tip_height: u32) -> bool {
+        self.claimable
+            .iter()
+            .chain(self.pending_sweep.iter())
+            .any(|c| {
+                c.awaiting_confirmation || c.unlock_height.is_some_and(|h| h <= tip_height + 1)
+            })
+    }
+}
+
+fn get_ln_balance_channel_amount(balance: &LightningBalance) -> (ChannelId, u64, Option<u32>) {
     match balance {
         LightningBalance::ClaimableOnChannelClose {
             channel_id,
             amount_satoshis,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (*channel_id, *amount_satoshis, None),
         LightningBalance::ClaimableAwaitingConfirmations {
             channel_id,
             amount_satoshis,
+            confirmation_height,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (*channel_id, *amount_satoshis, Some(*confirmation_height)),
         LightningBalance::ContentiousClaimable {
             channel_id,
             amount_satoshis,
+            timeout_height,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (*channel_id, *amount_satoshis, Some(*timeout_height)),
         LightningBalance::MaybeTimeoutClaimableHTLC {
             channel_id,
             amount_satoshis,
+            claimable_height,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (*channel_id, *amount_satoshis, Some(*claimable_height)),
         LightningBalance::MaybePreimageClaimableHTLC {
             channel_id,
             amount_satoshis,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (*channel_id, *amount_satoshis, None),
         LightningBalance::CounterpartyRevokedOutputClaimable {
             channel_id,
             amount_satoshis,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (*channel_id, *amount_satoshis, None),
     }
 }
 
-fn get_pending_sweep_balance_amount(amount: &PendingSweepBalance) -> (Option<ChannelId>, u64) {
+fn get_pending_sweep_balance_amount(
+    amount: &PendingSweepBalance,
+) -> (Option<ChannelId>, u64, Option<u32>, bool) {
     match amount {
         PendingSweepBalance::PendingBroadcast {
             channel_id,
             amount_satoshis,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (*channel_id, *amount_satoshis, None, true),
         PendingSweepBalance::BroadcastAwaitingConfirmation {
             channel_id,
             amount_satoshis,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (*channel_id, *amount_satoshis, None, true),
         PendingSweepBalance::AwaitingThresholdConfirmations {
             channel_id,
             amount_satoshis,
+            confirmation_height,
             ..
-        } => (*channel_id, *amount_satoshis),
+        } => (
+            *channel_id,
+            *amount_satoshis,
+            Some(confirmation_height + ANTI_REORG_DELAY - 1),
+            false,
+        ),
     }
 }
 
-pub fn check_and_print_balances(node: &Node, scb_channels: &[ChannelBackup]) -> u64 {
+pub fn collect_balances(node: &Node, scb_channels: &[ChannelBackup]) -> BalanceReport {
     let channels = node.list_channels();
     let balances = node.list_balances();
 
     let backup_by_channel: HashMap<_, _> = scb_channels
         .iter()
-        .map(|c| (c.channel_id.to_string(), c.clone()))
+        .map(|c| (c.channel_id.to_string(), c))
         .collect();
 
     let channel_ids = channels
@@ -76,86 +143,54 @@ pub fn check_and_print_balances(node: &Node, scb_channels: &[ChannelBackup]) ->
         .map(|c| c.channel_id)
         .collect::<HashSet<_>>();
 
-    let claimable_by_channel: Vec<_> = balances
+    let channel_amount =
+        |channel_id: Option<ChannelId>, amount, unlock_height, awaiting, awaiting_close| {
+            let channel_id = channel_id.map(|id| hex::encode(id.0));
+            let backup = channel_id.as_ref().and_then(|id| backup_by_channel.get(id));
+            ChannelAmount {
+                peer_id: backup.map(|b| b.peer_id.to_string()),
+                funding_tx: backup.map(|b| b.funding_tx_id.to_string()),
+                channel_id,
+                amount,
+                unlock_height,
+                awaiting_confirmation: awaiting,
+                awaiting_close,
+            }
+        };
+
+    let claimable = balances
         .lightning_balances
         .iter()
-        .map(get_ln_balance_channel_amount)
+        .map(|b| {
+            let awaiting_close = matches!(b, LightningBalance::ClaimableOnChannelClose { .. });
+            (get_ln_balance_channel_amount(b), awaiting_close)
+        })
+        .filter(|((channel_id, _, _), _)| !channel_ids.contains(channel_id))
+        .map(|((channel_id, amount, unlock_height), awaiting_close)| {
+            channel_amount(
+                Some(channel_id),
+                amount,
+                unlock_height,
+                false,
+                awaiting_close,
+            )
+        })
         .collect();
 
-    let claimable = claimable_by_channel
-        .iter()
-        .filter_map(|(channel_id, amount)| channel_ids.contains(channel_id).not().then(|| *amount))
-        .reduce(|total, amount| total + amount)
-        .unwrap_or(0);
-
-    let pending_by_channel: Vec<_> = balances
+    let pending_sweep = balances
         .pending_balances_from_channel_closures
         .iter()
         .map(get_pending_sweep_balance_amount)
+        .map(|(channel_id, amount, unlock_height, awaiting)| {
+            channel_amount(channel_id, amount, unlock_height, awaiting, false)
+        })
         .collect();
 
-    let pending_sweep = pending_by_channel
-        .iter()
-        .map(|(_, amount)| *amount)
-        .reduce(|total, amount| total + amount)
-        .unwrap_or(0);
-
-    info!(
-        "balances: spendable: {}, reserved: {}, claimable: {}, pending sweep: {}",
-        balances.spendable_onchain_balance_sats,
-        balances.total_anchor_channels_reserve_sats,
+    BalanceReport {
+        spendable: balances.spendable_onchain_balance_sats,
+        total: balances.total_onchain_balance_sats - balances.total_anchor_channels_reserve_sats,
+        reserved: balances.total_anchor_channels_reserve_sats,
         claimable,
-        pending_sweep
-    );
-
-    println!("Balances (sats):");
-    println!(
-        "  Spendable: {}; total: {}; reserved: {}",
-        balances.spendable_onchain_balance_sats,
-        balances.total_onchain_balance_sats - balances.total_anchor_channels_reserve_sats,
-        balances.total_anchor_channels_reserve_sats
-    );
-    println!(
-        "  Pending from channel closures: {}",
-        claimable + pending_sweep
-    );
-
-    if !claimable_by_channel.is_empty() {
-        println!("  Claimable:");
-        for (channel_id, amount) in claimable_by_channel {
-            let (peer_id, funding_tx) = backup_by_channel
-                .get(&hex::encode(&channel_id.0))
-                .map(|backup| (backup.peer_id.to_string(), backup.funding_tx_id.to_string()))
-                .unwrap_or_else(|| ("<unknown>".to_string(), "<unknown>".to_string()));
-            println!(
-                "    {} sats from node {}, funding tx {}",
-                amount, peer_id, funding_tx
-            );
-        }
+        pending_sweep,
     }
-
-    if !pending_by_channel.is_empty() {
-        println!("  Pending sweep:");
-        for (channel_id, amount) in pending_by_channel {
-            if channel_id.is_none() {
-                println!("    {} sats (channel unknown)", amount);
-                continue;
-            }
-
-            let channel_id = hex::encode(channel_id.unwrap().0);
-
-            let (peer_id, funding_tx) = backup_by_channel
-                .get(&channel_id)
-                .map(|backup| (backup.peer_id.to_string(), backup.funding_tx_id.to_string()))
-                .unwrap_or_else(|| ("<unknown>".to_string(), "<unknown>".to_string()));
-            println!(
-                "    {} sats from node {}, funding tx {}",
-                amount, peer_id, funding_tx
-            );
-        }
-    }
-
-    println!();
-
-    claimable + pending_sweep
 }
@@ -0,0 +1,403 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use ldk_node::config::{ElectrumSyncConfig, EsploraSyncConfig};
+use ldk_node::Builder;
+use log::warn;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::proxy;
+
+/// Blockchain data source used by the LDK node.
+#[derive(Debug, Clone)]
+pub enum ChainSource {
+    Esplora(Url),
+    Electrum(Url),
+}
+
+impl ChainSource {
+    /// Sets the chain source on the node builder. Background syncing is
+    /// disabled; wallets are synced on our own schedule instead.
+    pub fn configure(&self, builder: &mut Builder) {
+        match self {
+            ChainSource::Esplora(url) => {
+                builder.set_chain_source_esplora(
+                    url.as_str().trim_end_matches('/').to_string(),
+                    Some(EsploraSyncConfig {
+                        background_sync_config: None,
+                    }),
+                );
+            }
+            ChainSource::Electrum(url) => {
+                builder.set_chain_source_electrum(
+                    url.as_str().to_string(),
+                    Some(ElectrumSyncConfig {
+                        background_sync_config: None,
+                    }),
+                );
+            }
+        }
+    }
+}
+
+impl fmt::Display for ChainSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainSource::Esplora(url) => write!(f, "Esplora server {}", proxy::redacted(url)),
+            ChainSource::Electrum(url) => {
+                write!(f, "Electrum server {}", proxy::redacted(url))
+            }
+        }
+    }
+}
+
+/// Parses an Electrum server URL in the `tcp://host:port` or `ssl://host:port`
+/// form.
+pub fn parse_electrum_url(s: &str) -> Result<Url> {
+    let url = Url::parse(s).map_err(|e| anyhow!("invalid Electrum server URL: {}", e))?;
+
+    if url.scheme() != "tcp" && url.scheme() != "ssl" {
+        return Err(anyhow!(
+            "unsupported Electrum server URL scheme \"{}\"; use tcp:// or ssl://",
+            url.scheme()
+        ));
+    }
+
+    if url.host_str().is_none() || url.port().is_none() {
+        return Err(anyhow!(
+            "Electrum server URL must include a host and a port, e.g. ssl://electrum.example.com:50002"
+        ));
+    }
+
+    Ok(url)
+}
+
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time after which the health checks still running count as failed, e.g. one
+/// stuck resolving a host name.
+const HEALTH_CHECK_DEADLINE: Duration = Duration::from_secs(15);
+
+/// HTTP client for requests to Esplora servers, through the proxy if one is
+/// configured.
+pub fn http_client(proxy: Option<&Url>, timeout: Duration) -> Result<reqwest::blocking::Client> {
+    let mut builder = reqwest::blocking::Client::builder().timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder
+            .proxy(reqwest::Proxy::all(proxy::http_proxy_url(proxy)).context("invalid proxy URL")?);
+    }
+    builder.build().context("failed to create HTTP client")
+}
+
+/// Number of consecutive failed syncs after which we try to switch to another
+/// chain source.
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+
+impl ChainSource {
+    /// Checks whether the chain source is reachable and serving chain data.
+    pub fn check_health(&self, proxy: Option<&Url>) -> Result<()> {
+        match self {
+            ChainSource::Esplora(url) => {
+                let client = http_client(proxy, HEALTH_CHECK_TIMEOUT)?;
+                let height = client
+                    .get(format!(
+                        "{}/blocks/tip/height",
+                        url.as_str().trim_end_matches('/')
+                    ))
+                    .send()
+                    .and_then(|r| r.error_for_status())
+                    .and_then(|r| r.text())
+                    .context("failed to query tip height")?;
+                height
+                    .trim()
+                    .parse::<u32>()
+                    .context("unexpected tip height response")?;
+                Ok(())
+            }
+            ChainSource::Electrum(url) => {
+                let host = url.host_str().ok_or(anyhow!("missing host"))?;
+                let port = url.port().ok_or(anyhow!("missing port"))?;
+                let addr = (host, port)
+                    .to_socket_addrs()
+                    .context("failed to resolve Electrum server address")?
+                    .next()
+                    .ok_or(anyhow!("Electrum server address did not resolve"))?;
+                let stream = TcpStream::connect_timeout(&addr, HEALTH_CHECK_TIMEOUT)
+                    .context("failed to connect to Electrum server")?;
+                stream.set_read_timeout(Some(HEALTH_CHECK_TIMEOUT))?;
+                stream.set_write_timeout(Some(HEALTH_CHECK_TIMEOUT))?;
+                if url.scheme() == "ssl" {
+                    let name = ServerName::try_from(host.to_string())
+                        .context("invalid Electrum server name")?;
+                    let connection = ClientConnection::new(tls_config()?, name)
+                        .context("failed to set up TLS")?;
+                    electrum_server_version(StreamOwned::new(connection, stream))
+                } else {
+                    electrum_server_version(stream)
+                }
+            }
+        }
+    }
+}
+
+fn tls_config() -> Result<Arc<ClientConfig>> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .context("failed to set up TLS")?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Sends a `server.version` request, which any Electrum server answers, and
+/// checks that the response is not an error.
+fn electrum_server_version<S: Read + Write>(mut stream: S) -> Result<()> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "method": "server.version",
+        "params": ["hub-recovery", "1.4"],
+    });
+    writeln!(stream, "{}", request)
+        .and_then(|_| stream.flush())
+        .context("failed to send request to Electrum server")?;
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .context("no response from Electrum server")?;
+    let response: Value =
+        serde_json::from_str(&line).context("unexpected response from Electrum server")?;
+    match response.get("result") {
+        Some(result) if !result.is_null() => Ok(()),
+        _ => Err(anyhow!(
+            "Electrum server returned an error: {}",
+            response.get("error").unwrap_or(&Value::Null)
+        )),
+    }
+}
+
+/// Set of configured chain sources with failover between them.
+///
+/// Failing syncs, and failing broadcasts of the transactions the caller sends
+/// through the node, are used as the signal to switch to another source. The
+/// node's own broadcasts of closing and sweep transactions do not report
+/// failures, so they are not taken into account.
+pub struct ChainBackends {
+    sources: Vec<ChainSource>,
+    proxy: Option<Url>,
+    active: usize,
+    consecutive_failures: u32,
+}
+
+impl ChainBackends {
+    pub fn new(sources: Vec<ChainSource>, proxy: Option<Url>) -> Result<Self> {
+        if sources.is_empty() {
+            return Err(anyhow!("no chain source configured"));
+        }
+
+        Ok(Self {
+            sources,
+            proxy,
+            active: 0,
+            consecutive_failures: 0,
+        })
+    }
+
+    pub fn active(&self) -> &ChainSource {
+        &self.sources[self.active]
+    }
+
+    pub fn has_alternatives(&self) -> bool {
+        self.sources.len() > 1
+    }
+
+    /// Makes the first healthy chain source, in order of preference, the
+    /// active one. Keeps the current one if none of them is healthy.
+    pub fn select_healthy(&mut self) -> &ChainSource {
+        if self.has_alternatives() {
+            if let Some(i) = self.find_healthy(0) {
+                self.active = i;
+            }
+        }
+        self.active()
+    }
+
+    /// Records the result of a wallet sync. Returns the chain source to switch
+    /// to if the active one keeps failing and a healthy alternative exists.
+    pub fn record_sync(&mut self, ok: bool) -> Option<&ChainSource> {
+        if ok {
+            self.consecutive_failures = 0;
+            return None;
+        }
+        self.consecutive_failures += 1;
+        self.switch_if_failing()
+    }
+
+    /// Records the result of a transaction broadcast made by the caller, such
+    /// as the final sweep, see [`ChainBackends::record_sync`]. Unlike a sync,
+    /// a broadcast is not retried on its own, so a failed one looks for
+    /// another chain source right away.
+    pub fn record_broadcast(&mut self, ok: bool) -> Option<&ChainSource> {
+        if ok {
+            self.consecutive_failures = 0;
+            return None;
+        }
+        self.consecutive_failures = MAX_CONSECUTIVE_FAILURES;
+        self.switch_if_failing()
+    }
+
+    fn switch_if_failing(&mut self) -> Option<&ChainSource> {
+        if self.consecutive_failures < MAX_CONSECUTIVE_FAILURES || !self.has_alternatives() {
+            return None;
+        }
+
+        // Start over the count in any case so that health checks are not
+        // repeated on every failed sync while all sources are down.
+        self.consecutive_failures = 0;
+
+        warn!(
+            "chain source {} keeps failing, looking for another one",
+            self.active()
+        );
+        match self.find_healthy(self.active + 1) {
+            Some(i) if i != self.active => {
+                self.active = i;
+                Some(self.active())
+            }
+            _ => {
+                warn!("no healthy alternative chain source found");
+                None
+            }
+        }
+    }
+
+    /// Describes the active chain source and whether it is working.
+    pub fn status(&self) -> String {
+        match self.consecutive_failures {
+            0 => self.active().to_string(),
+            n => format!(
+                "{} (last {} sync(s) failed, balances may be outdated)",
+                self.active(),
+                n
+            ),
+        }
+    }
+
+    /// Checks all chain sources at once and returns the first healthy one,
+    /// starting at `start`.
+    fn find_healthy(&self, start: usize) -> Option<usize> {
+        let (tx, rx) = mpsc::channel();
+        for (i, source) in self.sources.iter().enumerate() {
+            let (tx, source, proxy) = (tx.clone(), source.clone(), self.proxy.clone());
+            // A check that is still running at the deadline is left behind.
+            thread::spawn(move || {
+                let _ = tx.send((i, source.check_health(proxy.as_ref())));
+            });
+        }
+        drop(tx);
+
+        let deadline = Instant::now() + HEALTH_CHECK_DEADLINE;
+        let mut healthy = vec![None; self.sources.len()];
+        while let Ok((i, result)) =
+            rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if let Err(e) = &result {
+                warn!("chain source {} is unhealthy: {:#}", self.sources[i], e);
+            }
+            healthy[i] = Some(result.is_ok());
+        }
+        for (i, _) in healthy.iter().enumerate().filter(|(_, h)| h.is_none()) {
+            warn!("chain source {} did not respond in time", self.sources[i]);
+        }
+
+        (0..self.sources.len())
+            .map(|i| (start + i) % self.sources.len())
+            .find(|&i| healthy[i] == Some(true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// An Esplora server that only answers tip height requests.
+    fn esplora_server() -> ChainSource {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.read(&mut [0; 1024]);
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n100");
+            }
+        });
+        ChainSource::Esplora(Url::parse(&url).unwrap())
+    }
+
+    /// An Electrum server that answers every request with `response`.
+    fn electrum_server(response: &'static str) -> ChainSource {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.read(&mut [0; 1024]);
+                let _ = writeln!(stream, "{}", response);
+            }
+        });
+        ChainSource::Electrum(Url::parse(&url).unwrap())
+    }
+
+    fn unreachable_server() -> ChainSource {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        ChainSource::Esplora(Url::parse(&format!("http://{}", addr)).unwrap())
+    }
+
+    #[test]
+    fn test_failover() {
+        let healthy = esplora_server();
+        let mut backends =
+            ChainBackends::new(vec![unreachable_server(), healthy.clone()], None).unwrap();
+
+        for _ in 1..MAX_CONSECUTIVE_FAILURES {
+            assert!(backends.record_sync(false).is_none());
+        }
+        assert_eq!(
+            backends.record_sync(false).map(|s| s.to_string()),
+            Some(healthy.to_string())
+        );
+
+        // The active source is the only healthy one.
+        assert!(backends.record_broadcast(false).is_none());
+        assert_eq!(backends.active().to_string(), healthy.to_string());
+        assert_eq!(backends.select_healthy().to_string(), healthy.to_string());
+    }
+
+    #[test]
+    fn test_electrum_health() {
+        electrum_server(r#"{"jsonrpc":"2.0","id":0,"result":["ElectrumX 1.16.0","1.4"]}"#)
+            .check_health(None)
+            .unwrap();
+        let error = r#"{"jsonrpc":"2.0","id":0,"error":{"code":-32600,"message":"bad"}}"#;
+        assert!(electrum_server(error).check_health(None).is_err());
+        // Something listening on the port that does not speak the protocol.
+        assert!(electrum_server("HTTP/1.1 400 Bad Request")
+            .check_health(None)
+            .is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use serde::Deserialize;
+
+use crate::logging::LogConfig;
+
+pub const CONFIG_FILE: &str = "hub-recovery.toml";
+
+/// Contents of the `hub-recovery.toml` configuration file.
+///
+/// Every value is optional; command line arguments take precedence over the
+/// values in the file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub seed_file: Option<PathBuf>,
+    pub backup_file: Option<String>,
+    pub network: Option<String>,
+    pub non_interactive: Option<bool>,
+    pub tui: Option<bool>,
+    pub api_listen: Option<SocketAddr>,
+    pub data_dir: Option<PathBuf>,
+    pub verbosity: Option<u8>,
+
+    #[serde(default)]
+    pub chain: ChainConfig,
+
+    #[serde(default)]
+    pub sync: SyncConfig,
+
+    #[serde(default)]
+    pub sweep: SweepConfig,
+
+    /// Peer addresses to use instead of the ones in the backup file.
+    #[serde(default)]
+    pub peers: Vec<PeerOverride>,
+
+    #[serde(default)]
+    pub ldk: LdkConfig,
+
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
+    #[serde(default)]
+    pub log: LogConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    #[serde(default)]
+    pub esplora_servers: Vec<String>,
+    pub electrum_server: Option<String>,
+    pub proxy: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SyncConfig {
+    pub interval_min_secs: Option<u64>,
+    pub interval_max_secs: Option<u64>,
+}
+
+/// Shell commands run on recovery milestones. Each command receives a JSON
+/// payload describing the milestone on stdin.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    pub peer_connected: Option<String>,
+    pub channel_closed: Option<String>,
+    pub sweep_broadcast: Option<String>,
+    pub sweep_confirmed: Option<String>,
+    pub recovery_complete: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Only tells which hooks are set, as commands may hold tokens or
+/// credentials.
+impl fmt::Debug for HooksConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let set = |command: &Option<String>| command.as_ref().map(|_| "[REDACTED]");
+        f.debug_struct("HooksConfig")
+            .field("peer_connected", &set(&self.peer_connected))
+            .field("channel_closed", &set(&self.channel_closed))
+            .field("sweep_broadcast", &set(&self.sweep_broadcast))
+            .field("sweep_confirmed", &set(&self.sweep_confirmed))
+            .field("recovery_complete", &set(&self.recovery_complete))
+            .field("error", &set(&self.error))
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfig {
+    /// Address to send the recovered funds to once the recovery completes.
+    pub address: Option<String>,
+    /// Fee rate for the sweep transaction. Estimated by LDK if not set.
+    pub fee_rate_sat_per_vb: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PeerOverride {
+    pub node_id: String,
+    pub address: String,
+}
+
+/// Advanced settings passed through to `ldk_node::config::Config`.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LdkConfig {
+    #[serde(default)]
+    pub trusted_peers_0conf: Vec<String>,
+    pub probing_liquidity_limit_multiplier: Option<u64>,
+    pub anchor_channels: Option<AnchorChannelsConfig>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AnchorChannelsConfig {
+    #[serde(default)]
+    pub trusted_peers_no_reserve: Vec<String>,
+    pub per_channel_reserve_sats: Option<u64>,
+}
+
+impl ConfigFile {
+    /// Loads the configuration file. Returns `None` if the file does not exist.
+    pub fn try_load<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        if !path.try_exists().context("cannot access config file")? {
+            return Ok(None);
+        }
+
+        let s = std::fs::read_to_string(path).context("failed to read config file")?;
+        let config = toml::from_str(&s).context("failed to parse config file")?;
+        Ok(Some(config))
+    }
+}
+
+impl LdkConfig {
+    /// Builds the LDK node configuration with these settings applied on top
+    /// of the defaults.
+    pub fn to_ldk_config(&self) -> Result<ldk_node::config::Config> {
+        let mut config = ldk_node::config::Config {
+            trusted_peers_0conf: parse_node_ids(&self.trusted_peers_0conf)?,
+            ..Default::default()
+        };
+
+        if let Some(m) = self.probing_liquidity_limit_multiplier {
+            config.probing_liquidity_limit_multiplier = m;
+        }
+
+        if let Some(anchor) = &self.anchor_channels {
+            let mut anchor_config = config.anchor_channels_config.unwrap_or_default();
+            anchor_config.trusted_peers_no_reserve =
+                parse_node_ids(&anchor.trusted_peers_no_reserve)?;
+            if let Some(reserve) = anchor.per_channel_reserve_sats {
+                anchor_config.per_channel_reserve_sats = reserve;
+            }
+            config.anchor_channels_config = Some(anchor_config);
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_node_ids(ids: &[String]) -> Result<Vec<PublicKey>> {
+    ids.iter()
+        .map(|id| PublicKey::from_str(id).map_err(|e| anyhow!("invalid node ID {}: {}", id, e)))
+        .collect()
+}
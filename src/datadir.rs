@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::io::sqlite_store::{SqliteStore, KV_TABLE_NAME, SQLITE_DB_FILE_NAME};
+use ldk_node::lightning::util::persist::{
+    KVStoreSync, CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+    CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+
+use crate::scb;
+use crate::state::State;
+
+pub const LDK_DIR: &str = "ldk_data";
+pub const STATE_FILE: &str = "hub-recovery.state";
+pub const JOURNAL_FILE: &str = "hub-recovery.journal";
+
+/// Subdirectory holding one directory per recovery, named by node ID.
+const RECOVERIES_DIR: &str = "recoveries";
+
+/// Subdirectory holding the data of reset recoveries.
+const ARCHIVE_DIR: &str = "archive";
+
+/// Extension of the lock file next to each recovery directory, holding the ID
+/// of the process using the recovery.
+const LOCK_EXTENSION: &str = "lock";
+
+/// A recovery found in the data directory.
+pub struct RecoveryInfo {
+    pub node_id: String,
+    pub path: PathBuf,
+    /// The recovery state, or why it could not be loaded.
+    pub state: Result<Option<State>>,
+    pub updated: Option<SystemTime>,
+}
+
+/// Channel monitors in the LDK node store of a recovery directory, with their
+/// latest update IDs.
+pub fn stored_monitors(recovery_dir: &Path) -> Result<HashMap<String, Option<u64>>> {
+    let ldk_dir = recovery_dir.join(LDK_DIR);
+    if !ldk_dir
+        .join(SQLITE_DB_FILE_NAME)
+        .try_exists()
+        .unwrap_or(false)
+    {
+        return Ok(HashMap::new());
+    }
+
+    // The names the node uses; the defaults would open a new, empty database.
+    let store = SqliteStore::new(
+        ldk_dir,
+        Some(SQLITE_DB_FILE_NAME.to_string()),
+        Some(KV_TABLE_NAME.to_string()),
+    )
+    .context("failed to open LDK node store")?;
+    let keys = store
+        .list(
+            CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+            CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+        )
+        .context("failed to list stored channel monitors")?;
+
+    keys.into_iter()
+        .map(|key| {
+            let value = store
+                .read(
+                    CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+                    CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+                    &key,
+                )
+                .with_context(|| format!("failed to read stored channel monitor {}", key))?;
+            Ok((key, scb::monitor_update_id(&value)))
+        })
+        .collect()
+}
+
+/// Advisory lock that keeps other instances of the tool from using a recovery
+/// at the same time. Released when dropped, or by the OS if the process dies.
+pub struct DataLock {
+    _file: File,
+}
+
+/// Data directory in which each recovery is kept in its own directory, so that
+/// recoveries of several nodes can share it.
+pub struct DataDir {
+    root: PathBuf,
+}
+
+impl DataDir {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Locks the recovery of the node with the given ID, failing if another
+    /// process holds the lock. The lock file is kept beside the recovery
+    /// directory, so that the directory can be removed on a reset.
+    pub fn lock_recovery(&self, node_id: &PublicKey) -> Result<DataLock> {
+        self.lock(node_id, true)
+    }
+
+    /// Locks the recovery of the node with the given ID for reading, failing
+    /// if another process holds the lock of [`Self::lock_recovery`]. Any
+    /// number of processes can hold this lock at the same time.
+    pub fn lock_recovery_shared(&self, node_id: &PublicKey) -> Result<DataLock> {
+        self.lock(node_id, false)
+    }
+
+    fn lock(&self, node_id: &PublicKey, exclusive: bool) -> Result<DataLock> {
+        let dir = self.root.join(RECOVERIES_DIR);
+        fs::create_dir_all(&dir).context("failed to create recoveries directory")?;
+        let path = dir.join(node_id.to_string()).with_extension(LOCK_EXTENSION);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("failed to open lock file {}", path.display()))?;
+
+        let locked = if exclusive {
+            file.try_lock()
+        } else {
+            file.try_lock_shared()
+        };
+        match locked {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                // The PID cannot be read on systems where locks are mandatory.
+                let mut pid = String::new();
+                let holder = match file.read_to_string(&mut pid) {
+                    Ok(_) if !pid.trim().is_empty() => format!(" (PID {})", pid.trim()),
+                    _ => String::new(),
+                };
+                return Err(anyhow!(
+                    "the recovery of node {} is in use by another instance of hub-recovery{}",
+                    node_id,
+                    holder
+                ));
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("failed to lock {}", path.display()));
+            }
+        }
+
+        // Readers leave the PID of the last writer in place.
+        if exclusive {
+            file.set_len(0)
+                .and_then(|_| file.write_all(process::id().to_string().as_bytes()))
+                .with_context(|| format!("failed to write lock file {}", path.display()))?;
+        }
+
+        Ok(DataLock { _file: file })
+    }
+
+    /// Directory for the recovery of the node with the given ID.
+    pub fn recovery_dir(&self, node_id: &PublicKey) -> PathBuf {
+        self.root.join(RECOVERIES_DIR).join(node_id.to_string())
+    }
+
+    /// Recovery data that a reset of the given recovery moves to the archive.
+    pub fn recovery_data(&self, node_id: &PublicKey) -> Vec<PathBuf> {
+        let dir = self.recovery_dir(node_id);
+        [
+            dir.join(STATE_FILE),
+            dir.join(JOURNAL_FILE),
+            dir.join(LDK_DIR),
+        ]
+        .into_iter()
+        .filter(|p| p.try_exists().unwrap_or(true))
+        .collect()
+    }
+
+    /// Moves the data of the given recovery into a new timestamped directory
+    /// in the archive, so that the recovery starts from scratch without losing
+    /// the wallet data. Requires the lock, so that the data cannot be moved
+    /// while another instance is using it. Returns the archive directory.
+    pub fn archive_recovery(&self, node_id: &PublicKey, _lock: &DataLock) -> Result<PathBuf> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let archive = self
+            .root
+            .join(ARCHIVE_DIR)
+            .join(format!("{}-{}", node_id, timestamp));
+        fs::create_dir_all(&archive).context("failed to create archive directory")?;
+
+        let dir = self.recovery_dir(node_id);
+        for name in [STATE_FILE, JOURNAL_FILE, LDK_DIR] {
+            let from = dir.join(name);
+            if from.try_exists().unwrap_or(false) {
+                fs::rename(&from, archive.join(name))
+                    .with_context(|| format!("failed to move {}", from.display()))?;
+            }
+        }
+        // Only succeeds if nothing else was stored there.
+        let _ = fs::remove_dir(dir);
+
+        Ok(archive)
+    }
+
+    pub fn list(&self) -> Result<Vec<RecoveryInfo>> {
+        let dir = self.root.join(RECOVERIES_DIR);
+        if !dir.try_exists().unwrap_or(false) {
+            return Ok(Vec::new());
+        }
+
+        let mut recoveries = Vec::new();
+        for entry in fs::read_dir(&dir).context("failed to read recoveries directory")? {
+            let entry = entry.context("failed to read recoveries directory")?;
+            if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+
+            let path = entry.path();
+            let state_path = path.join(STATE_FILE);
+            recoveries.push(RecoveryInfo {
+                node_id: entry.file_name().to_string_lossy().into_owned(),
+                // A corrupt state file must not hide the other recoveries.
+                state: State::try_load(&state_path).with_context(|| {
+                    format!("failed to load recovery state {}", state_path.display())
+                }),
+                updated: fs::metadata(&state_path).and_then(|m| m.modified()).ok(),
+                path,
+            });
+        }
+        recoveries.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+
+        Ok(recoveries)
+    }
+
+    /// Existing recovery data in the layout used before recoveries got their
+    /// own directories.
+    pub fn legacy_paths(&self) -> Vec<PathBuf> {
+        [self.root.join(STATE_FILE), self.root.join(LDK_DIR)]
+            .into_iter()
+            .filter(|p| p.try_exists().unwrap_or(false))
+            .collect()
+    }
+
+    pub fn load_legacy_state(&self) -> Result<Option<State>> {
+        State::try_load(self.root.join(STATE_FILE)).context("failed to load legacy recovery state")
+    }
+
+    /// Moves recovery data in the legacy layout into the given recovery
+    /// directory.
+    pub fn migrate_legacy(&self, recovery_dir: &Path) -> Result<()> {
+        fs::create_dir_all(recovery_dir).context("failed to create recovery directory")?;
+        for name in [STATE_FILE, LDK_DIR] {
+            let from = self.root.join(name);
+            if from.try_exists().unwrap_or(false) {
+                fs::rename(&from, recovery_dir.join(name))
+                    .with_context(|| format!("failed to move {}", from.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_recovery() {
+        let dir =
+            DataDir::new(std::env::temp_dir().join(format!("hub-recovery-lock-{}", process::id())));
+        let node: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+            .parse()
+            .unwrap();
+        let other: PublicKey = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"
+            .parse()
+            .unwrap();
+
+        let lock = dir.lock_recovery(&node).unwrap();
+        let err = dir.lock_recovery(&node).err().unwrap();
+        assert!(
+            err.to_string().contains(&process::id().to_string()),
+            "{}",
+            err
+        );
+        assert!(dir.lock_recovery_shared(&node).is_err());
+        // Other recoveries in the same data directory are not locked.
+        let other_lock = dir.lock_recovery(&other).unwrap();
+
+        drop(lock);
+        drop(other_lock);
+        let shared = dir.lock_recovery_shared(&node).unwrap();
+        let other_shared = dir.lock_recovery_shared(&node).unwrap();
+        assert!(dir.lock_recovery(&node).is_err());
+        drop(shared);
+        drop(other_shared);
+        dir.lock_recovery(&node).unwrap();
+        fs::remove_dir_all(dir.root()).unwrap();
+    }
+
+    #[test]
+    fn test_list_with_corrupt_state() {
+        let dir =
+            DataDir::new(std::env::temp_dir().join(format!("hub-recovery-list-{}", process::id())));
+        let node: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+            .parse()
+            .unwrap();
+        let other: PublicKey = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"
+            .parse()
+            .unwrap();
+        fs::create_dir_all(dir.recovery_dir(&node)).unwrap();
+        fs::write(dir.recovery_dir(&node).join(STATE_FILE), "{").unwrap();
+        fs::create_dir_all(dir.recovery_dir(&other)).unwrap();
+        State::new()
+            .save(dir.recovery_dir(&other).join(STATE_FILE))
+            .unwrap();
+
+        let recoveries = dir.list().unwrap();
+        fs::remove_dir_all(dir.root()).unwrap();
+        assert_eq!(recoveries.len(), 2);
+        assert!(recoveries[0].state.is_err());
+        assert_eq!(recoveries[1].state.as_ref().unwrap(), &Some(State::new()));
+    }
+
+    #[test]
+    fn test_stored_monitors() {
+        let dir = std::env::temp_dir().join(format!("hub-recovery-datadir-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // Written the way the LDK node persists its channel monitors.
+        let store = SqliteStore::new(
+            dir.join(LDK_DIR),
+            Some(SQLITE_DB_FILE_NAME.to_string()),
+            Some(KV_TABLE_NAME.to_string()),
+        )
+        .unwrap();
+        let mut monitor = vec![1, 1];
+        monitor.extend(42u64.to_be_bytes());
+        store
+            .write(
+                CHANNEL_MONITOR_PERSISTENCE_PRIMARY_NAMESPACE,
+                CHANNEL_MONITOR_PERSISTENCE_SECONDARY_NAMESPACE,
+                "txid_0",
+                monitor,
+            )
+            .unwrap();
+        drop(store);
+
+        let monitors = stored_monitors(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(monitors, HashMap::from([("txid_0".to_string(), Some(42))]));
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+use std::process::ExitCode;
+
+/// Failure categories that are reported through distinct exit codes.
+///
+/// Attach one of them as context to an error to classify it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Required input was not provided and could not be prompted for.
+    MissingInput,
+    /// The static channel backup file is missing or malformed.
+    BadBackup,
+    /// The static channel backup does not match the recovery in progress.
+    BackupChanged,
+    /// The seed phrase is invalid or does not belong to the backup.
+    WrongSeed,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::MissingInput => write!(f, "missing input"),
+            Failure::BadBackup => write!(f, "bad static channel backup"),
+            Failure::BackupChanged => write!(f, "static channel backup has changed"),
+            Failure::WrongSeed => write!(f, "wrong seed phrase"),
+        }
+    }
+}
+
+/// How a recovery run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// All funds have been recovered.
+    Completed,
+    /// The run was interrupted before the recovery completed.
+    Interrupted,
+    /// The run was interrupted and some peers could not be connected to.
+    PeerFailures,
+}
+
+pub const EXIT_COMPLETED: u8 = 0;
+pub const EXIT_ERROR: u8 = 1;
+pub const EXIT_MISSING_INPUT: u8 = 2;
+pub const EXIT_IN_PROGRESS: u8 = 3;
+pub const EXIT_PEER_FAILURES: u8 = 4;
+pub const EXIT_BAD_BACKUP: u8 = 5;
+pub const EXIT_WRONG_SEED: u8 = 6;
+
+impl Outcome {
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Outcome::Completed => EXIT_COMPLETED,
+            Outcome::Interrupted => EXIT_IN_PROGRESS,
+            Outcome::PeerFailures => EXIT_PEER_FAILURES,
+        })
+    }
+}
+
+pub fn exit_code(e: &anyhow::Error) -> ExitCode {
+    ExitCode::from(match e.downcast_ref::<Failure>() {
+        Some(Failure::MissingInput) => EXIT_MISSING_INPUT,
+        Some(Failure::BadBackup | Failure::BackupChanged) => EXIT_BAD_BACKUP,
+        Some(Failure::WrongSeed) => EXIT_WRONG_SEED,
+        None => EXIT_ERROR,
+    })
+}
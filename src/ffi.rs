@@ -0,0 +1,291 @@
+//! Bindings of the backup functions and the recovery engine for other
+//! languages, generated with UniFFI.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use ldk_node::bip39::Mnemonic;
+use ldk_node::bitcoin::Network;
+use log::{error, info};
+use url::Url;
+use zeroize::Zeroizing;
+
+use crate::chain::{self, ChainSource};
+use crate::datadir::{DataDir, DataLock};
+use crate::failure::Failure;
+use crate::progress::{PeerStatus, Snapshot};
+use crate::recovery::{Recovery, RecoveryConfig};
+use crate::scb::{self, BackupSummary};
+use crate::seed::{self, SeedError};
+
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum RecoveryError {
+    /// The backup is malformed or has no channels.
+    BadBackup(String),
+    /// The backup does not match the recovery in progress.
+    BackupChanged(String),
+    /// The seed phrase is invalid or does not belong to the backup.
+    WrongSeed(String),
+    Failed(String),
+}
+
+impl fmt::Display for RecoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecoveryError::BadBackup(msg)
+            | RecoveryError::BackupChanged(msg)
+            | RecoveryError::WrongSeed(msg)
+            | RecoveryError::Failed(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for RecoveryError {}
+
+impl From<anyhow::Error> for RecoveryError {
+    fn from(e: anyhow::Error) -> Self {
+        error!("{:?}", e);
+        let msg = format!("{:#}", e);
+        match e.downcast_ref::<Failure>() {
+            Some(Failure::BadBackup) => RecoveryError::BadBackup(msg),
+            Some(Failure::BackupChanged) => RecoveryError::BackupChanged(msg),
+            Some(Failure::WrongSeed) => RecoveryError::WrongSeed(msg),
+            Some(Failure::MissingInput) | None => RecoveryError::Failed(msg),
+        }
+    }
+}
+
+/// Settings of a recovery session. At least one chain source is required.
+#[derive(uniffi::Record)]
+pub struct SessionConfig {
+    /// Directory for the recovery data. Each recovery is kept in a
+    /// subdirectory named by the node ID.
+    pub data_dir: String,
+    /// `bitcoin`, `testnet`, `signet` or `regtest`.
+    pub network: String,
+    pub esplora_servers: Vec<String>,
+    pub electrum_server: Option<String>,
+    /// SOCKS5 proxy, e.g. `socks5://127.0.0.1:9050` for Tor.
+    pub proxy: Option<String>,
+    /// Connect to peers without an onion address directly even though a
+    /// proxy is set, revealing the IP address to them.
+    #[uniffi(default = false)]
+    pub allow_direct_peers: bool,
+    /// Addresses that override the ones in the backup, by peer node ID.
+    pub peer_addresses: HashMap<String, String>,
+    pub sync_interval_min_secs: u64,
+    pub sync_interval_max_secs: u64,
+}
+
+impl SessionConfig {
+    fn to_recovery_config(&self) -> anyhow::Result<RecoveryConfig> {
+        let network = Network::from_str(&self.network)
+            .map_err(|_| anyhow!("invalid network: {}", self.network))?;
+
+        let mut chain_sources = self
+            .esplora_servers
+            .iter()
+            .map(|s| Url::parse(s).map(ChainSource::Esplora))
+            .collect::<Result<Vec<_>, _>>()
+            .context("invalid Esplora server")?;
+        if let Some(url) = &self.electrum_server {
+            if !chain_sources.is_empty() {
+                return Err(anyhow!(
+                    "Esplora and Electrum servers cannot be used at the same time"
+                ));
+            }
+            let url = chain::parse_electrum_url(url).context("invalid Electrum server")?;
+            chain_sources.push(ChainSource::Electrum(url));
+        }
+        if chain_sources.is_empty() {
+            return Err(anyhow!("no chain source given"));
+        }
+
+        let proxy = self
+            .proxy
+            .as_deref()
+            .map(crate::proxy::parse_proxy_url)
+            .transpose()
+            .context("invalid proxy")?;
+
+        if self.sync_interval_min_secs == 0 || self.sync_interval_max_secs == 0 {
+            return Err(anyhow!("sync intervals must be at least 1 second"));
+        }
+
+        Ok(RecoveryConfig {
+            network,
+            chain_sources,
+            proxy,
+            allow_direct_peers: self.allow_direct_peers,
+            peer_addresses: self.peer_addresses.clone(),
+            ldk_config: ldk_node::config::Config::default(),
+            sync_interval_min: Duration::from_secs(self.sync_interval_min_secs),
+            sync_interval_max: Duration::from_secs(self.sync_interval_max_secs),
+        })
+    }
+}
+
+fn parse_mnemonic(seed_phrase: &str) -> anyhow::Result<Mnemonic> {
+    // The message may end up in the caller's logs.
+    seed::parse_mnemonic(seed_phrase)
+        .map_err(SeedError::redacted)
+        .context("invalid seed phrase")
+        .context(Failure::WrongSeed)
+}
+
+/// Derives the ID of the node that uses the given seed phrase.
+#[uniffi::export]
+pub fn derive_node_id(seed_phrase: String) -> Result<String, RecoveryError> {
+    let seed_phrase = Zeroizing::new(seed_phrase);
+    let mnemonic = parse_mnemonic(&seed_phrase)?;
+    Ok(scb::derive_node_id(&mnemonic).to_string())
+}
+
+/// Decrypts and parses a backup, given as the contents of a plain or
+/// encrypted backup file, and checks that it belongs to the seed phrase.
+#[uniffi::export]
+pub fn inspect_backup(backup: String, seed_phrase: String) -> Result<BackupSummary, RecoveryError> {
+    let seed_phrase = Zeroizing::new(seed_phrase);
+    let mnemonic = parse_mnemonic(&seed_phrase)?;
+    Ok(scb::inspect(&backup, &mnemonic)?)
+}
+
+/// A recovery driven from another language. Call `poll` regularly until
+/// `is_complete`; the recovery can be stopped and resumed at any time.
+#[derive(uniffi::Object)]
+pub struct RecoverySession {
+    recovery: Mutex<Recovery>,
+    /// Held by `poll` for the whole wallet sync, during which the recovery
+    /// itself stays available to the other methods.
+    polling: Mutex<()>,
+    /// Set by `stop`, with the recovery locked.
+    stopped: AtomicBool,
+    _lock: DataLock,
+}
+
+impl RecoverySession {
+    fn recovery(&self) -> Result<MutexGuard<'_, Recovery>, RecoveryError> {
+        // A panic while the recovery was in use may have left it half
+        // updated, so the session cannot be used any more.
+        self.recovery.lock().map_err(|_| {
+            RecoveryError::Failed(
+                "the recovery session failed; start a new session to resume".to_string(),
+            )
+        })
+    }
+}
+
+#[uniffi::export]
+impl RecoverySession {
+    /// Opens the recovery of the backup, given as the contents of a plain or
+    /// encrypted backup file. A recovery in progress is resumed.
+    #[uniffi::constructor]
+    pub fn new(
+        config: SessionConfig,
+        seed_phrase: String,
+        backup: String,
+    ) -> Result<Arc<Self>, RecoveryError> {
+        let seed_phrase = Zeroizing::new(seed_phrase);
+        let mnemonic = parse_mnemonic(&seed_phrase)?;
+        let scb = scb::parse_scb_guess_type(&backup, &mnemonic)?;
+        scb::check_node_id(&scb, &mnemonic)?;
+
+        let data_dir = DataDir::new(config.data_dir.clone().into());
+        std::fs::create_dir_all(data_dir.root()).context("failed to create data directory")?;
+        let lock = data_dir.lock_recovery(&scb::derive_node_id(&mnemonic))?;
+        let recovery = Recovery::new(
+            config.to_recovery_config()?,
+            &data_dir,
+            mnemonic,
+            scb,
+            &mut (),
+        )?;
+        Ok(Arc::new(Self {
+            recovery: Mutex::new(recovery),
+            polling: Mutex::new(()),
+            stopped: AtomicBool::new(false),
+            _lock: lock,
+        }))
+    }
+
+    /// Whether the recovery was already in progress.
+    pub fn is_resumed(&self) -> Result<bool, RecoveryError> {
+        Ok(self.recovery()?.is_resumed())
+    }
+
+    /// Starts the node and connects to the peers, which then force close the
+    /// channels.
+    pub fn start(&self) -> Result<(), RecoveryError> {
+        let mut recovery = self.recovery()?;
+        recovery.start(&mut ())?;
+        recovery.connect_peers(&mut ())?;
+        Ok(())
+    }
+
+    /// Handles the pending events of the node and synchronizes the wallets
+    /// if due, then returns the progress of the recovery. Returns nothing once
+    /// the session has been stopped, also by a `stop` during the sync.
+    pub fn poll(&self) -> Result<Option<Snapshot>, RecoveryError> {
+        // Only one sync runs at a time. The lock guards no data, so a panic
+        // in an earlier poll does not matter.
+        let _polling = self.polling.lock().unwrap_or_else(|e| e.into_inner());
+
+        let node = {
+            let mut recovery = self.recovery()?;
+            if self.stopped.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let node = Arc::clone(recovery.node().context("recovery has not been started")?);
+            while let Some(event) = node.next_event() {
+                recovery.handle_event(&event, &mut ())?;
+                node.event_handled()
+                    .map_err(|e| anyhow!("failed to mark event as handled: {:?}", e))?;
+            }
+            recovery.sync_due()?
+        };
+
+        // The sync can take minutes, so it runs without the lock.
+        if let Some(node) = node {
+            info!("syncing wallets");
+            let sync_result = node.sync_wallets();
+            self.recovery()?.finish_sync(sync_result, &mut ())?;
+        }
+        // `stop` may have been called during the sync.
+        let recovery = self.recovery()?;
+        if self.stopped.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        Ok(Some(recovery.snapshot()?))
+    }
+
+    /// Seconds until the next wallet sync is due.
+    pub fn secs_until_next_sync(&self) -> Result<u64, RecoveryError> {
+        Ok(self.recovery()?.time_until_next_sync().as_secs())
+    }
+
+    pub fn is_complete(&self) -> Result<bool, RecoveryError> {
+        Ok(self.recovery()?.is_complete())
+    }
+
+    pub fn peers(&self) -> Result<Vec<PeerStatus>, RecoveryError> {
+        Ok(self.recovery()?.peer_statuses()?)
+    }
+
+    /// Peers that could not be connected to.
+    pub fn failed_peers(&self) -> Result<Vec<String>, RecoveryError> {
+        Ok(self.recovery()?.failed_peers().iter().cloned().collect())
+    }
+
+    /// Stops the node. The recovery can be resumed later with a new session.
+    pub fn stop(&self) -> Result<(), RecoveryError> {
+        let mut recovery = self.recovery()?;
+        self.stopped.store(true, Ordering::Relaxed);
+        Ok(recovery.stop()?)
+    }
+}
@@ -0,0 +1,234 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use serde_json::{json, Value};
+
+use crate::config::HooksConfig;
+use crate::progress::{self, ChannelRow, ChannelStatus};
+
+/// Time after which a hook command is killed.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Recovery milestones that hook commands can be configured for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    PeerConnected,
+    ChannelClosed,
+    SweepBroadcast,
+    SweepConfirmed,
+    RecoveryComplete,
+    Error,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Hook::PeerConnected => "peer_connected",
+            Hook::ChannelClosed => "channel_closed",
+            Hook::SweepBroadcast => "sweep_broadcast",
+            Hook::SweepConfirmed => "sweep_confirmed",
+            Hook::RecoveryComplete => "recovery_complete",
+            Hook::Error => "error",
+        }
+    }
+}
+
+/// Runs the configured hook commands in the background.
+pub struct Hooks {
+    config: HooksConfig,
+    running: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Hooks {
+    pub fn new(config: HooksConfig) -> Self {
+        Self {
+            config,
+            running: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn command(&self, hook: Hook) -> Option<&String> {
+        match hook {
+            Hook::PeerConnected => self.config.peer_connected.as_ref(),
+            Hook::ChannelClosed => self.config.channel_closed.as_ref(),
+            Hook::SweepBroadcast => self.config.sweep_broadcast.as_ref(),
+            Hook::SweepConfirmed => self.config.sweep_confirmed.as_ref(),
+            Hook::RecoveryComplete => self.config.recovery_complete.as_ref(),
+            Hook::Error => self.config.error.as_ref(),
+        }
+    }
+
+    /// Starts the command configured for the hook, if any, with a JSON
+    /// payload on stdin. `details` must be a JSON object.
+    pub fn fire(&self, hook: Hook, details: Value) {
+        let Some(command) = self.command(hook).cloned() else {
+            return;
+        };
+
+        let mut payload = json!({
+            "hook": hook.name(),
+            "timestamp": progress::now(),
+        });
+        if let (Some(payload), Value::Object(details)) = (payload.as_object_mut(), details) {
+            payload.extend(details);
+        }
+
+        info!("running {} hook", hook.name());
+        let handle = thread::spawn(move || {
+            if let Err(e) = run_command(&command, &payload.to_string()) {
+                error!("{} hook failed: {:#}", hook.name(), e);
+            }
+        });
+        self.running.lock().unwrap().push(handle);
+    }
+
+    /// Fires the channel hooks for the milestones reached between two
+    /// snapshots of the channels.
+    pub fn fire_channel_milestones(&self, old: &[ChannelRow], new: &[ChannelRow]) {
+        for (hook, row) in channel_milestones(old, new) {
+            self.fire(
+                hook,
+                json!({
+                    "channel_id": row.channel_id,
+                    "peer_id": row.peer_id,
+                    "status": row.status,
+                    "amount_sats": row.amount,
+                    "unlock_height": row.unlock_height,
+                }),
+            );
+        }
+    }
+
+    /// Waits for the running hook commands to finish.
+    pub fn finish(&self) {
+        for handle in self.running.lock().unwrap().drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// The channel milestones reached between two snapshots of the channels.
+fn channel_milestones<'a>(
+    old: &[ChannelRow],
+    new: &'a [ChannelRow],
+) -> Vec<(Hook, &'a ChannelRow)> {
+    let mut milestones = Vec::new();
+    for row in new {
+        let Some(before) = old.iter().find(|r| r.channel_id == row.channel_id) else {
+            continue;
+        };
+
+        // A stage may be skipped between two syncs, so look for stages that
+        // were passed rather than entered.
+        let passed = |stage| before.status < stage && row.status >= stage;
+        if !before.status.is_closed_on_chain() && row.status.is_closed_on_chain() {
+            milestones.push((Hook::ChannelClosed, row));
+        }
+        // A channel without funds of the node has none pending once closed,
+        // so only funds that were on their way before were swept.
+        let swept = row.status != ChannelStatus::NoFundsPending
+            || (before.status.is_closed_on_chain() && before.amount > 0);
+        if swept {
+            if passed(ChannelStatus::SweepConfirming) {
+                milestones.push((Hook::SweepBroadcast, row));
+            }
+            if passed(ChannelStatus::SweepFinalizing) {
+                milestones.push((Hook::SweepConfirmed, row));
+            }
+        }
+    }
+    milestones
+}
+
+fn run_command(command: &str, payload: &str) -> Result<()> {
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The command may not read its input; that is not an error.
+        let _ = stdin.write_all(payload.as_bytes());
+    }
+
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                warn!("hook command exited with {}", status);
+            }
+            return Ok(());
+        }
+        if started.elapsed() > HOOK_TIMEOUT {
+            child.kill()?;
+            return Err(anyhow!("timed out after {:?}", HOOK_TIMEOUT));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(status: ChannelStatus, amount: u64) -> ChannelRow {
+        ChannelRow {
+            channel_id: "aa".to_string(),
+            peer_id: "bb".to_string(),
+            status,
+            amount,
+            unlock_height: None,
+        }
+    }
+
+    fn hooks(before: ChannelRow, after: ChannelRow) -> Vec<Hook> {
+        channel_milestones(&[before], &[after])
+            .into_iter()
+            .map(|(hook, _)| hook)
+            .collect()
+    }
+
+    #[test]
+    fn test_channel_milestones() {
+        use ChannelStatus::*;
+
+        assert_eq!(hooks(row(Closing, 1_000), row(Closing, 1_000)), vec![]);
+        assert_eq!(
+            hooks(row(Closing, 1_000), row(Timelocked, 1_000)),
+            vec![Hook::ChannelClosed]
+        );
+        assert_eq!(
+            hooks(row(Timelocked, 1_000), row(SweepFinalizing, 1_000)),
+            vec![Hook::SweepBroadcast, Hook::SweepConfirmed]
+        );
+        // The sweep confirmed and became final between two syncs.
+        assert_eq!(
+            hooks(row(SweepConfirming, 1_000), row(NoFundsPending, 0)),
+            vec![Hook::SweepConfirmed]
+        );
+        // Nothing to sweep.
+        assert_eq!(
+            hooks(row(WaitingForPeer, 0), row(NoFundsPending, 0)),
+            vec![Hook::ChannelClosed]
+        );
+    }
+}
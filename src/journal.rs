@@ -0,0 +1,140 @@
+//! Append-only record of what happened during a recovery, from which the
+//! recovery report is built. Each line of the journal file is one JSON entry.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::progress;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JournalEvent {
+    /// The peer was connected to, which makes it force close the channel.
+    ForceCloseRequested { channel_id: String, peer_id: String },
+    ChannelClosed {
+        channel_id: String,
+        reason: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp: u64,
+    /// Height of the chain tip when the entry was recorded.
+    pub height: u32,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+pub struct Journal {
+    path: PathBuf,
+    entries: Vec<JournalEntry>,
+    /// The file ends in a partial line, which the next entry must not be
+    /// appended to.
+    needs_newline: bool,
+}
+
+impl Journal {
+    /// Opens the journal at the given path, which is created on the first
+    /// entry. Lines that cannot be read, e.g. one cut short by a crash, are
+    /// skipped.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let contents = if path.try_exists().context("cannot access journal file")? {
+            fs::read_to_string(&path).context("failed to read journal file")?
+        } else {
+            String::new()
+        };
+
+        let mut entries = Vec::new();
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("skipping unreadable journal entry {:?}: {}", line, e),
+            }
+        }
+        Ok(Self {
+            path,
+            entries,
+            needs_newline: !contents.is_empty() && !contents.ends_with('\n'),
+        })
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub fn record(&mut self, height: u32, event: JournalEvent) -> Result<()> {
+        let entry = JournalEntry {
+            timestamp: progress::now(),
+            height,
+            event,
+        };
+        let mut line = if self.needs_newline {
+            "\n".to_string()
+        } else {
+            String::new()
+        };
+        line.push_str(&serde_json::to_string(&entry)?);
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .context("failed to write journal file")?;
+        self.needs_newline = false;
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_reopen() {
+        let path =
+            std::env::temp_dir().join(format!("hub-recovery-journal-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut journal = Journal::open(&path).unwrap();
+        journal
+            .record(
+                100,
+                JournalEvent::ForceCloseRequested {
+                    channel_id: "aa".to_string(),
+                    peer_id: "bb".to_string(),
+                },
+            )
+            .unwrap();
+        // A partially written entry, as left behind by a crash.
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(b"{\"timestamp\":1"))
+            .unwrap();
+
+        let mut reopened = Journal::open(&path).unwrap();
+        assert_eq!(reopened.entries(), journal.entries());
+        reopened
+            .record(
+                101,
+                JournalEvent::ChannelClosed {
+                    channel_id: "aa".to_string(),
+                    reason: None,
+                },
+            )
+            .unwrap();
+
+        let entries = Journal::open(&path).unwrap().entries;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries, reopened.entries);
+    }
+}
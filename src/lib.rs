@@ -0,0 +1,39 @@
+//! Recovery of the funds in the channels of an Alby Hub from its static
+//! channel backup and seed phrase.
+//!
+//! [`Recovery`] runs a recovery and reports its progress to an [`Observer`].
+//! The `hub-recovery` command line tool is one frontend for it. Without the
+//! default `node` feature, only the backup functions in [`scb`] are built.
+
+#[cfg(feature = "node")]
+pub mod balance;
+#[cfg(feature = "node")]
+pub mod chain;
+#[cfg(feature = "node")]
+pub mod datadir;
+pub mod failure;
+#[cfg(feature = "uniffi")]
+pub mod ffi;
+#[cfg(feature = "node")]
+pub mod journal;
+#[cfg(feature = "node")]
+pub mod progress;
+#[cfg(feature = "node")]
+pub mod proxy;
+#[cfg(feature = "node")]
+pub mod recovery;
+#[cfg(feature = "node")]
+pub mod report;
+pub mod scb;
+#[cfg(feature = "node")]
+pub mod schedule;
+pub mod seed;
+pub mod state;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "node")]
+pub use recovery::{Observer, Recovery, RecoveryConfig};
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use log::LevelFilter;
+use log4rs::append::console::{ConsoleAppender, Target};
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::config::{Appender, Config, Root};
+use log4rs::encode::json::JsonEncoder;
+use log4rs::encode::pattern::PatternEncoder;
+use log4rs::encode::Encode;
+use serde::Deserialize;
+
+const DEFAULT_MAX_SIZE_MB: u64 = 10;
+const DEFAULT_MAX_FILES: u32 = 5;
+
+#[derive(clap::ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Logging settings from the `[log]` section of the configuration file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: Option<LogFormat>,
+    /// Mirror log records to stderr.
+    pub stderr: Option<bool>,
+    /// Size at which the log file is rotated.
+    pub max_size_mb: Option<u64>,
+    /// Number of rotated log files to keep.
+    pub max_files: Option<u32>,
+}
+
+impl LogConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_size_mb == Some(0) || self.max_files == Some(0) {
+            return Err(anyhow!("log max_size_mb and max_files must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+fn encoder(format: LogFormat) -> Box<dyn Encode> {
+    match format {
+        LogFormat::Text => Box::new(PatternEncoder::new(
+            "[{d(%Y-%m-%dT%H:%M:%S%.3f%z)} {l} {t}] {m}{n}",
+        )),
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    }
+}
+
+/// Sets up logging to a size-rotated file at `path` and, optionally, stderr.
+/// Rotated files are named `<path>.1`, `<path>.2` and so on, oldest last.
+pub fn setup(
+    path: &Path,
+    verbosity: u8,
+    format: LogFormat,
+    stderr: bool,
+    config: &LogConfig,
+) -> Result<()> {
+    let level = match verbosity {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    let max_size = config.max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024;
+    let roller = FixedWindowRoller::builder()
+        .build(
+            &format!("{}.{{}}", path.display()),
+            config.max_files.unwrap_or(DEFAULT_MAX_FILES),
+        )
+        .map_err(|e| anyhow!("failed to create log roller: {}", e))?;
+    let policy = CompoundPolicy::new(Box::new(SizeTrigger::new(max_size)), Box::new(roller));
+
+    let logfile = RollingFileAppender::builder()
+        .encoder(encoder(format))
+        .build(path, Box::new(policy))
+        .context("failed to create log file appender")?;
+
+    let mut config =
+        Config::builder().appender(Appender::builder().build("logfile", Box::new(logfile)));
+    let mut root = Root::builder().appender("logfile");
+
+    if stderr {
+        let console = ConsoleAppender::builder()
+            .target(Target::Stderr)
+            .encoder(encoder(format))
+            .build();
+        config = config.appender(Appender::builder().build("stderr", Box::new(console)));
+        root = root.appender("stderr");
+    }
+
+    let config = config
+        .build(root.build(level))
+        .context("failed to create log configuration")?;
+
+    log4rs::init_config(config).context("failed to initialize log4rs")?;
+
+    Ok(())
+}
@@ -1,95 +1,318 @@
-use std::collections::HashSet;
-use std::io;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::str::FromStr;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use ldk_node;
+use clap::{Parser, Subcommand};
 use ldk_node::bip39::Mnemonic;
+use ldk_node::bitcoin::address::NetworkUnchecked;
 use ldk_node::bitcoin::secp256k1::PublicKey;
-use ldk_node::bitcoin::Network;
+use ldk_node::bitcoin::{Address, FeeRate, Network};
 use ldk_node::lightning::ln::msgs::SocketAddress;
-use log::{error, info, LevelFilter};
-use log4rs::append::file::FileAppender;
-use log4rs::config::{Appender, Config, Root};
-use log4rs::encode::pattern::PatternEncoder;
+use ldk_node::{Event, Node, NodeError};
+use log::{debug, error, info, warn};
+use serde_json::json;
 use url::Url;
+use zeroize::Zeroizing;
+
+mod api;
+mod config;
+mod hooks;
+mod logging;
+mod metrics;
+mod secret;
+mod tui;
+
+use hub_recovery::{balance, chain, failure, progress, proxy, report, scb, seed};
+
+use api::ApiServer;
+use chain::{ChainBackends, ChainSource};
+use config::{ConfigFile, HooksConfig, LdkConfig, CONFIG_FILE};
+use failure::{Failure, Outcome};
+use hooks::{Hook, Hooks};
+use hub_recovery::balance::{BalanceReport, ChannelAmount};
+use hub_recovery::datadir::{DataDir, JOURNAL_FILE, LDK_DIR};
+use hub_recovery::journal::Journal;
+use hub_recovery::progress::{PeerStatus, Snapshot};
+use hub_recovery::recovery::{self, Observer, Recovery, RecoveryConfig};
+use hub_recovery::state::ChannelState;
+use logging::{LogConfig, LogFormat};
+use metrics::Metrics;
+use scb::StaticChannelBackup;
+use secret::Secret;
+use seed::SeedError;
 
-mod balance;
-mod scb;
-mod state;
-
-use scb::EncodedChannelMonitorBackup;
-use state::{ChannelState, State};
-
-const LDK_DIR: &str = "./ldk_data";
 const LOG_FILE: &str = "hub-recovery.log";
-const STATE_FILE: &str = "hub-recovery.state";
 const DEFAULT_SCB_FILE: &str = "channel-backup.json";
 const DEFAULT_SCB_ENCRYPTED_FILE: &str = "channel-backup.enc";
+const SEED_ENV_VAR: &str = "HUB_RECOVERY_SEED";
+const DEFAULT_ESPLORA_SERVER: &str = "https://electrs.getalbypro.com";
+const DEFAULT_SYNC_INTERVAL_MIN: u64 = 30;
+const DEFAULT_SYNC_INTERVAL_MAX: u64 = 300;
+/// How often the event listener checks for new events of the node.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Recover funds from the channels in an Alby Hub static channel backup."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Force-close the channels in the backup and wait until all funds are
+    /// recovered. This is the default command.
+    Recover,
+
+    /// Show the recovery progress from the local data, without connecting to
+    /// peers or the chain source.
+    Status,
+
+    /// Show the contents of the static channel backup file.
+    Inspect,
+
+    /// Send the recovered on-chain funds to an address.
+    Withdraw {
+        /// Destination address. Defaults to the configured sweep address.
+        address: Option<Address<NetworkUnchecked>>,
+
+        /// Fee rate in sat/vB. Estimated if not set.
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        fee_rate: Option<u64>,
+
+        /// Withdraw even if funds of closed channels are still pending. The
+        /// reserve needed to claim them stays in the wallet.
+        #[arg(long)]
+        force: bool,
+
+        /// Do not ask for confirmation.
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Move the local recovery state to the archive to start the recovery
+    /// from scratch.
+    Reset {
+        /// Node ID of the recovery to reset. Derived from the seed phrase if
+        /// not set.
+        #[arg(long)]
+        node_id: Option<PublicKey>,
+
+        /// Do not ask for confirmation.
+        #[arg(long)]
+        yes: bool,
+
+        /// Reset even if the recovery still holds funds, or if its balances
+        /// cannot be checked because --node-id is given.
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// List the recoveries in the data directory.
+    List,
+
+    /// Write a per-channel report of the recovery, e.g. for bookkeeping.
+    Report {
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = ReportFormat::Csv)]
+        format: ReportFormat,
+
+        /// Write the report to a file instead of stdout.
+        #[arg(short = 'o', long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum ReportFormat {
+    Csv,
+    Json,
+}
+
+/// Options shared by all commands.
+#[derive(clap::Args)]
 struct Args {
-    /// Seed phrase. If you do not provide a value, you will be prompted to enter it.
-    #[arg(short = 's', long)]
-    seed: Option<Mnemonic>,
+    /// Seed phrase. Deprecated, as the seed phrase is then visible in the
+    /// process list; use --seed-file or the environment variable instead. If no
+    /// seed phrase is provided, you will be prompted to enter it.
+    #[arg(short = 's', long, env = SEED_ENV_VAR, hide_env_values = true, global = true)]
+    seed: Option<Secret<String>>,
+
+    /// Whether the seed phrase was set in the environment, from which it is
+    /// removed at startup.
+    #[arg(skip)]
+    seed_from_env: bool,
+
+    /// Read the seed phrase from a file. Use "-" to read it from stdin.
+    #[arg(long, global = true)]
+    seed_file: Option<PathBuf>,
 
     /// Path to the Alby Hub static channel backup file.
-    #[arg(short = 'b', long)]
+    #[arg(short = 'b', long, global = true)]
     backup_file: Option<String>,
 
-    /// LDK network.
-    #[arg(short = 'n', long, default_value = "bitcoin")]
-    ldk_network: Network,
+    /// LDK network [default: bitcoin].
+    #[arg(short = 'n', long, global = true)]
+    ldk_network: Option<Network>,
 
-    /// Esplora server URL.
-    #[arg(long, default_value = "https://electrs.getalbypro.com")]
-    esplora_server: Url,
+    /// Esplora server URL [default: https://electrs.getalbypro.com]. Can be
+    /// specified multiple times (or as a comma-separated list) to fail over to
+    /// the next server when the current one stops responding.
+    #[arg(long, value_delimiter = ',', global = true)]
+    esplora_server: Vec<Url>,
 
-    /// Reset local recovery state.
+    /// Electrum server URL (tcp://host:port or ssl://host:port). If set, it is
+    /// used as the chain source instead of the Esplora server.
+    #[arg(long, value_parser = chain::parse_electrum_url, conflicts_with = "esplora_server", global = true)]
+    electrum_server: Option<Url>,
+
+    /// SOCKS5 proxy URL, e.g. socks5://127.0.0.1:9050 for a local Tor daemon.
     ///
-    /// WARNING: the recovery process will start from scratch. All the existing
-    /// recovery state will be lost.
-    #[arg(long)]
-    reset_recovery: bool,
+    /// Esplora requests and connections to peers with onion addresses are
+    /// routed through the proxy; peers with other addresses are not connected
+    /// to unless --allow-direct-peers is given. Cannot be combined with an
+    /// Electrum server.
+    #[arg(long, value_parser = proxy::parse_proxy_url, conflicts_with = "electrum_server", global = true)]
+    proxy: Option<Url>,
+
+    /// With --proxy, connect to peers without an onion address directly,
+    /// revealing your IP address to them.
+    #[arg(long, requires = "proxy", global = true)]
+    allow_direct_peers: bool,
+
+    /// Shortest interval between wallet syncs, in seconds [default: 30]. Used
+    /// while transactions are about to confirm.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), global = true)]
+    sync_interval_min: Option<u64>,
+
+    /// Longest interval between wallet syncs, in seconds [default: 300]. Syncs
+    /// back off to this interval while nothing changes.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), global = true)]
+    sync_interval_max: Option<u64>,
+
+    /// Address to send the recovered funds to once the recovery completes.
+    #[arg(long, global = true)]
+    sweep_address: Option<Address<NetworkUnchecked>>,
+
+    /// Fee rate for the sweep transaction, in sat/vB. Estimated if not set.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), global = true)]
+    sweep_fee_rate: Option<u64>,
 
     /// Use the current working directory for local data instead of the
     /// directory where the executable is located.
-    #[arg(long)]
+    #[arg(long, global = true)]
     use_workdir: bool,
 
+    /// Directory for recovery data and the log file [default: the directory
+    /// where the executable is located, or the working directory with
+    /// --use-workdir]. Each recovery is kept in a subdirectory named by the
+    /// node ID.
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Never prompt for input. Missing inputs are reported as errors and the
+    /// result of the recovery is reported through the exit code.
+    #[arg(long, global = true)]
+    non_interactive: bool,
+
+    /// Show the recovery progress on a full-screen dashboard.
+    #[arg(long, global = true)]
+    tui: bool,
+
+    /// Serve the recovery status as JSON on this address, e.g.
+    /// 127.0.0.1:8080. Endpoints: /status, /events, /peers, /health and
+    /// /metrics (Prometheus).
+    #[arg(long, global = true)]
+    api_listen: Option<SocketAddr>,
+
+    /// Path to the configuration file [default: hub-recovery.toml in the
+    /// local data directory]. Command line arguments take precedence over
+    /// values from the file.
+    #[arg(short = 'c', long, global = true)]
+    config: Option<PathBuf>,
+
     /// Enable verbose output. Specify once for debug level, twice for trace level.
-    #[arg(short = 'v', action = clap::ArgAction::Count)]
+    #[arg(short = 'v', action = clap::ArgAction::Count, global = true)]
     verbosity: u8,
-}
 
-fn setup_logging(verbosity: u8) -> Result<()> {
-    let level = match verbosity {
-        0 => LevelFilter::Info,
-        1 => LevelFilter::Debug,
-        _ => LevelFilter::Trace,
-    };
+    /// Log file format.
+    #[arg(long, value_enum, global = true)]
+    log_format: Option<LogFormat>,
 
-    let logfile = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(
-            "[{d(%Y-%m-%dT%H:%M:%S%.3f%z)} {l} {t}] {m}{n}",
-        )))
-        .build(LOG_FILE)
-        .context("failed to create log file appender")?;
+    /// Also write log records to stderr.
+    #[arg(long, global = true)]
+    log_stderr: bool,
 
-    let config = Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .build(Root::builder().appender("logfile").build(level))
-        .context("failed to create log configuration")?;
+    /// Log rotation settings from the configuration file.
+    #[arg(skip)]
+    log_config: LogConfig,
 
-    log4rs::init_config(config).context("failed to initialize log4rs")?;
+    /// Peer addresses to use instead of the ones in the backup, by peer ID.
+    #[arg(skip)]
+    peer_addresses: HashMap<String, String>,
 
-    Ok(())
+    /// Advanced LDK settings from the configuration file.
+    #[arg(skip)]
+    ldk_config: LdkConfig,
+
+    /// Commands to run on recovery milestones.
+    #[arg(skip)]
+    hooks: HooksConfig,
+}
+
+/// Written out to keep the passwords of URLs out of the log; the seed phrase
+/// and the hook commands redact themselves.
+impl fmt::Debug for Args {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let esplora_servers: Vec<_> = self.esplora_server.iter().map(proxy::redacted).collect();
+        f.debug_struct("Args")
+            .field("seed", &self.seed)
+            .field("seed_from_env", &self.seed_from_env)
+            .field("seed_file", &self.seed_file)
+            .field("backup_file", &self.backup_file)
+            .field("ldk_network", &self.ldk_network)
+            .field("esplora_server", &esplora_servers)
+            .field(
+                "electrum_server",
+                &self.electrum_server.as_ref().map(proxy::redacted),
+            )
+            .field("proxy", &self.proxy.as_ref().map(proxy::redacted))
+            .field("allow_direct_peers", &self.allow_direct_peers)
+            .field("sync_interval_min", &self.sync_interval_min)
+            .field("sync_interval_max", &self.sync_interval_max)
+            .field("sweep_address", &self.sweep_address)
+            .field("sweep_fee_rate", &self.sweep_fee_rate)
+            .field("use_workdir", &self.use_workdir)
+            .field("data_dir", &self.data_dir)
+            .field("non_interactive", &self.non_interactive)
+            .field("tui", &self.tui)
+            .field("api_listen", &self.api_listen)
+            .field("config", &self.config)
+            .field("verbosity", &self.verbosity)
+            .field("log_format", &self.log_format)
+            .field("log_stderr", &self.log_stderr)
+            .field("log_config", &self.log_config)
+            .field("peer_addresses", &self.peer_addresses)
+            .field("ldk_config", &self.ldk_config)
+            .field("hooks", &self.hooks)
+            .finish()
+    }
 }
 
 fn prompt(p: &str) -> String {
@@ -99,33 +322,85 @@ fn prompt(p: &str) -> String {
     input.trim().to_string()
 }
 
-fn prompt_parse<T>(p: &str) -> T
-where
-    T: FromStr,
-{
-    loop {
-        match prompt(p).parse::<T>() {
-            Ok(v) => break v,
-            Err(_) => println!("Incorrect input, try again"),
-        }
+/// Reads a line without echoing it. Falls back to plain input when there is no
+/// terminal to read from.
+fn prompt_hidden(p: &str) -> Zeroizing<String> {
+    match rpassword::prompt_password(format!("{}\n", p)) {
+        Ok(input) => Zeroizing::new(input),
+        Err(_) => Zeroizing::new(prompt(p)),
     }
 }
 
-fn parse_peer_address(s: &str) -> Result<SocketAddress> {
-    SocketAddress::from_str(s).map_err(|e| {
-        anyhow!(
-            "bad static channel backup: invalid peer address {}: {:?}",
-            s,
-            e
-        )
-    })
+fn missing_input(msg: &str) -> anyhow::Error {
+    anyhow::Error::msg(msg.to_string()).context(Failure::MissingInput)
+}
+
+/// Takes the seed phrase out of `args`, so that it is wiped once parsed.
+fn get_mnemonic(args: &mut Args) -> Result<Mnemonic> {
+    // Parsed here rather than by clap, which would echo the phrase in errors.
+    if let Some(seed) = args.seed.take() {
+        if !args.seed_from_env {
+            warn!("seed phrase passed on the command line");
+            eprintln!(
+                "Warning: --seed is deprecated, as other users can see the seed phrase in the process list. Use --seed-file or the {} environment variable instead.",
+                SEED_ENV_VAR
+            );
+        }
+        return seed::parse_mnemonic(&seed)
+            .map_err(SeedError::redacted)
+            .context("invalid seed phrase")
+            .context(Failure::WrongSeed);
+    }
+
+    if let Some(path) = &args.seed_file {
+        let seed = if path.as_os_str() == "-" {
+            let mut seed = Zeroizing::new(String::new());
+            io::stdin()
+                .read_to_string(&mut seed)
+                .context("failed to read seed phrase from stdin")?;
+            seed
+        } else {
+            Zeroizing::new(
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read seed file {}", path.display()))?,
+            )
+        };
+
+        return seed::parse_mnemonic(&seed)
+            .map_err(SeedError::redacted)
+            .context("invalid seed phrase")
+            .context(Failure::WrongSeed);
+    }
+
+    if args.non_interactive {
+        return Err(missing_input(&format!(
+            "no seed phrase provided; use --seed-file or the {} environment variable",
+            SEED_ENV_VAR
+        )));
+    }
+
+    loop {
+        let input = prompt_hidden(
+            "Enter recovery phrase (12, 15, 18, 21 or 24 words separated by spaces, input is hidden):",
+        );
+        match seed::parse_mnemonic(&input) {
+            Ok(mnemonic) => {
+                println!(
+                    "Recovery phrase accepted ({} words).",
+                    mnemonic.word_count()
+                );
+                break Ok(mnemonic);
+            }
+            Err(e) => println!("Invalid recovery phrase: {}. Try again.", e),
+        }
+    }
 }
 
-fn get_scb_path<P: AsRef<Path>>(dir: P, arg: Option<&str>) -> PathBuf {
+fn get_scb_path<P: AsRef<Path>>(dir: P, args: &Args) -> Result<PathBuf> {
     let dir = dir.as_ref();
 
-    if let Some(p) = arg {
-        return dir.join(p);
+    if let Some(p) = &args.backup_file {
+        return Ok(dir.join(p));
     }
 
     let detected_default = if let Ok(true) = dir.join(DEFAULT_SCB_FILE).try_exists() {
@@ -136,6 +411,15 @@ fn get_scb_path<P: AsRef<Path>>(dir: P, arg: Option<&str>) -> PathBuf {
         None
     };
 
+    if args.non_interactive {
+        return detected_default.map(|d| dir.join(d)).ok_or_else(|| {
+            missing_input(&format!(
+                "no static channel backup file provided and neither {} nor {} found",
+                DEFAULT_SCB_FILE, DEFAULT_SCB_ENCRYPTED_FILE
+            ))
+        });
+    }
+
     let prompt_str = match detected_default {
         Some(p) => format!(
             "Enter static channel backup filename (press enter to use default filename: \"{}\"):",
@@ -148,7 +432,7 @@ fn get_scb_path<P: AsRef<Path>>(dir: P, arg: Option<&str>) -> PathBuf {
         let p = prompt(&prompt_str);
         if p.trim().is_empty() {
             if let Some(d) = detected_default {
-                break dir.join(d);
+                break Ok(dir.join(d));
             } else {
                 println!("No filename provided, please try again");
                 continue;
@@ -157,247 +441,1040 @@ fn get_scb_path<P: AsRef<Path>>(dir: P, arg: Option<&str>) -> PathBuf {
 
         let path = PathBuf::from(&p);
         if path.try_exists().unwrap_or(false) {
-            break path;
+            break Ok(path);
         } else if dir.join(&p).try_exists().unwrap_or(false) {
-            break dir.join(&p);
+            break Ok(dir.join(&p));
         } else {
             println!("File {} not found, please try again", p);
         }
     }
 }
 
-fn run<P: AsRef<Path>>(args: &Args, dir: P) -> Result<()> {
-    let dir = dir.as_ref();
-    let mut state = State::try_load(dir.join(STATE_FILE))
-        .context("failed to load recovery state")?
-        .unwrap_or_default();
+impl Args {
+    /// Fills in the values not given on the command line from the
+    /// configuration file.
+    fn apply_config(&mut self, config: ConfigFile) -> Result<()> {
+        fn parse<T: FromStr>(key: &str, value: Option<String>) -> Result<Option<T>>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value
+                .map(|v| {
+                    v.parse::<T>()
+                        .map_err(|e| anyhow!("invalid value for {}: {}", key, e))
+                })
+                .transpose()
+        }
 
-    if !state.is_empty() {
-        println!("Recovery process is in progress.");
-        loop {
-            let s =
-                prompt("Hit Enter to resume recovery. Type NEW to start the process from scratch.");
-            if s.trim().is_empty() {
-                break;
-            } else if s.trim().to_lowercase() == "new" {
-                reset_recovery(dir)?;
-                state = State::default();
-                break;
-            } else {
-                println!("Invalid input, try again");
-            }
+        self.seed_file = self.seed_file.take().or(config.seed_file);
+        self.backup_file = self.backup_file.take().or(config.backup_file);
+        self.non_interactive |= config.non_interactive.unwrap_or(false);
+        self.tui |= config.tui.unwrap_or(false);
+        self.data_dir = self.data_dir.take().or(config.data_dir);
+        self.api_listen = self.api_listen.or(config.api_listen);
+        if self.verbosity == 0 {
+            self.verbosity = config.verbosity.unwrap_or(0);
+        }
+        if self.ldk_network.is_none() {
+            self.ldk_network = parse("network", config.network)?;
         }
-    }
 
-    let first_run = state.is_empty();
+        // Chain sources are taken as a whole: if any is given on the command
+        // line, the ones from the file are ignored.
+        if self.esplora_server.is_empty() && self.electrum_server.is_none() {
+            self.esplora_server = config
+                .chain
+                .esplora_servers
+                .into_iter()
+                .map(|s| parse("chain.esplora_servers", Some(s)).map(Option::unwrap))
+                .collect::<Result<_>>()?;
+            self.electrum_server = config
+                .chain
+                .electrum_server
+                .map(|s| chain::parse_electrum_url(&s).context("invalid chain.electrum_server"))
+                .transpose()?;
+        }
+        if self.proxy.is_none() {
+            self.proxy = config
+                .chain
+                .proxy
+                .map(|s| proxy::parse_proxy_url(&s).context("invalid chain.proxy"))
+                .transpose()?;
+        }
 
-    let mnemonic = args.seed.clone().unwrap_or_else(|| {
-        const SAMPLE: &str =
-            "hotel obvious agent lecture gadget evil jealous keen fragile before damp clarify";
-        let prompt = format!("Enter recovery phrase (12 words, e.g.: {}):", SAMPLE);
-        prompt_parse(&prompt)
-    });
+        self.sync_interval_min = self.sync_interval_min.or(config.sync.interval_min_secs);
+        self.sync_interval_max = self.sync_interval_max.or(config.sync.interval_max_secs);
 
-    let scb_path = get_scb_path(dir, args.backup_file.as_deref());
+        if self.sweep_address.is_none() {
+            self.sweep_address = parse("sweep.address", config.sweep.address)?;
+        }
+        self.sweep_fee_rate = self.sweep_fee_rate.or(config.sweep.fee_rate_sat_per_vb);
+
+        self.peer_addresses = config
+            .peers
+            .into_iter()
+            .map(|p| (p.node_id, p.address))
+            .collect();
+        self.log_format = self.log_format.or(config.log.format);
+        self.log_stderr |= config.log.stderr.unwrap_or(false);
+        self.log_config = config.log;
+        self.ldk_config = config.ldk;
+        self.hooks = config.hooks;
+
+        Ok(())
+    }
 
-    let scb = scb::load_scb_guess_type(scb_path, &mnemonic)
-        .context("failed to load static channel backup file")?;
+    /// Checks the combinations of arguments that clap cannot validate on its
+    /// own because some of them may come from the configuration file.
+    fn validate(&self) -> Result<()> {
+        if !self.esplora_server.is_empty() && self.electrum_server.is_some() {
+            return Err(anyhow!(
+                "Esplora and Electrum servers cannot be used at the same time"
+            ));
+        }
+        if self.proxy.is_some() && self.electrum_server.is_some() {
+            return Err(anyhow!("a proxy cannot be used with an Electrum server"));
+        }
+        if matches!(
+            (self.sync_interval_min, self.sync_interval_max),
+            (Some(0), _) | (_, Some(0))
+        ) {
+            return Err(anyhow!("sync intervals must be at least 1 second"));
+        }
+        if self.tui && self.non_interactive {
+            return Err(anyhow!(
+                "the dashboard cannot be used in non-interactive mode"
+            ));
+        }
+        if self.tui && self.log_stderr {
+            return Err(anyhow!(
+                "logging to stderr cannot be used with the dashboard"
+            ));
+        }
+        self.log_config.validate()?;
+        self.sweep_address()?;
+        Ok(())
+    }
 
-    if state.is_empty() {
-        info!("initializing recovery state");
-        scb.channels.iter().for_each(|ch| {
-            state.set_channel_state(&ch.peer_id, &ch.channel_id, ChannelState::Pending);
-        });
-        state
-            .save(dir.join(STATE_FILE))
-            .context("failed to save recovery state")?;
-    } else if state.get_all_channel_ids() != scb.channel_ids() {
-        // If the channels in SCB don't match channels in the recovery state
-        // file, it is likely that the recovery process has been restarted
-        // with a different static channel backup file. We do not allow that.
-        error!("static channel backup file has changed; cannot proceed with the recovery");
-        println!("The recovery process has already been initiated with a different static channel backup file.");
-        println!("Please specify the same backup file to resume recovery.");
-        println!("To recover channels from a different backup file, restart the app with the --reset-recovery flag.");
-        println!("WARNING: this will reset the recovery state and start the recovery process from scratch.");
-        return Err(anyhow!(
-            "static channel backup file does not match the stored state"
-        ));
-    }
-
-    let mut builder = ldk_node::Builder::new();
-    builder
-        .set_entropy_bip39_mnemonic(mnemonic, None)
-        .set_network(args.ldk_network)
-        .set_storage_dir_path(
-            dir.join(LDK_DIR)
-                .to_str()
-                .ok_or(anyhow!("invalid LDK path"))?
-                .to_string(),
-        )
-        .set_chain_source_esplora(
-            args.esplora_server
-                .to_string()
-                .trim_end_matches('/')
-                .to_string(),
-            None,
+    fn network(&self) -> Network {
+        self.ldk_network.unwrap_or(Network::Bitcoin)
+    }
+
+    fn sync_intervals(&self) -> (Duration, Duration) {
+        (
+            Duration::from_secs(self.sync_interval_min.unwrap_or(DEFAULT_SYNC_INTERVAL_MIN)),
+            Duration::from_secs(self.sync_interval_max.unwrap_or(DEFAULT_SYNC_INTERVAL_MAX)),
         )
-        .set_log_facade_logger();
+    }
 
-    if first_run {
-        builder.restore_encoded_channel_monitors(
-            scb.monitors
-                .into_iter()
-                .map(EncodedChannelMonitorBackup::into)
+    fn recovery_config(&self) -> Result<RecoveryConfig> {
+        let (sync_interval_min, sync_interval_max) = self.sync_intervals();
+        Ok(RecoveryConfig {
+            network: self.network(),
+            chain_sources: self.chain_sources(),
+            proxy: self.proxy.clone(),
+            allow_direct_peers: self.allow_direct_peers,
+            peer_addresses: self.peer_addresses.clone(),
+            ldk_config: self
+                .ldk_config
+                .to_ldk_config()
+                .context("invalid LDK settings in config file")?,
+            sync_interval_min,
+            sync_interval_max,
+        })
+    }
+
+    fn sweep_address(&self) -> Result<Option<Address>> {
+        self.sweep_address
+            .clone()
+            .map(|a| {
+                a.require_network(self.network())
+                    .context("sweep address is not valid for the selected network")
+            })
+            .transpose()
+    }
+
+    fn chain_sources(&self) -> Vec<ChainSource> {
+        match &self.electrum_server {
+            Some(url) => vec![ChainSource::Electrum(url.clone())],
+            None if self.esplora_server.is_empty() => {
+                vec![ChainSource::Esplora(
+                    Url::parse(DEFAULT_ESPLORA_SERVER).unwrap(),
+                )]
+            }
+            None => self
+                .esplora_server
+                .iter()
+                .cloned()
+                .map(ChainSource::Esplora)
                 .collect(),
+        }
+    }
+}
+
+/// Loads the static channel backup and checks that it belongs to the seed.
+fn load_backup(args: &Args, dir: &Path, mnemonic: &Mnemonic) -> Result<StaticChannelBackup> {
+    let scb_path = get_scb_path(dir, args)?;
+
+    let scb = scb::load_scb_guess_type(scb_path, mnemonic)
+        .context("failed to load static channel backup file")?;
+
+    scb::check_node_id(&scb, mnemonic).inspect_err(|_| {
+        println!("The seed phrase does not belong to the node that created this backup.");
+    })?;
+
+    Ok(scb)
+}
+
+fn recover(
+    args: &mut Args,
+    local_dir: &Path,
+    data_dir: &DataDir,
+    hooks: &Hooks,
+) -> Result<Outcome> {
+    let mnemonic = get_mnemonic(args)?;
+    let _lock = data_dir.lock_recovery(&scb::derive_node_id(&mnemonic))?;
+
+    let scb = load_backup(args, local_dir, &mnemonic)?;
+
+    let config = args.recovery_config()?;
+    let stale_after = config.sync_interval_max * 3;
+    let metrics = Arc::new(Metrics::new());
+    let mut frontend = Frontend::new(hooks, Arc::clone(&metrics), &config);
+
+    let mut recovery = match Recovery::new(config, data_dir, mnemonic, scb, &mut frontend) {
+        Err(e) if e.downcast_ref::<Failure>() == Some(&Failure::BackupChanged) => {
+            println!("The recovery process has already been initiated with a different static channel backup file.");
+            println!("Please specify the same backup file to resume recovery.");
+            println!("To recover channels from a different backup file, reset the recovery first: hub-recovery reset");
+            println!("WARNING: this will reset the recovery state and start the recovery process from scratch.");
+            return Err(e);
+        }
+        r => r?,
+    };
+
+    if recovery.is_resumed() {
+        println!("Recovery process is in progress, resuming.");
+        println!("To start the recovery from scratch instead, run: hub-recovery reset");
+    }
+
+    recovery.start(&mut frontend)?;
+
+    println!("Found {} channel(s) in backup.", recovery.channels().len());
+    recovery.connect_peers(&mut frontend)?;
+
+    if !recovery.failed_peers().is_empty() {
+        println!("Failed to connect to the following peers:");
+        for peer in recovery.failed_peers() {
+            println!("  {}", peer);
+        }
+        println!("Please check the logs for details.");
+    }
+
+    if args.tui {
+        frontend.dashboard = Some(tui::Dashboard::enter()?);
+    } else {
+        println!("Waiting for channel recovery to complete. This may take a while...");
+        println!("It is safe to interrupt this program by pressing Ctrl-C. You can resume it later to check recovery status.");
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let interrupt_tx = tx.clone();
+    ctrlc::set_handler(move || {
+        interrupt_tx
+            .send(Signal::Interrupt)
+            .expect("Could not send signal on channel.")
+    })
+    .expect("Error setting Ctrl-C handler");
+    let node = recovery.node().context("recovery has not been started")?;
+    let mut event_listener = EventListener::spawn(Arc::clone(node), tx.clone());
+    if frontend.dashboard.is_some() {
+        spawn_input_listener(tx.clone());
+    }
+
+    frontend.api = args
+        .api_listen
+        .map(|addr| ApiServer::start(addr, stale_after, metrics))
+        .transpose()?;
+
+    recovery.report_progress(&mut frontend)?;
+    let outcome = loop {
+        if recovery.is_complete() {
+            info!("no more pending funds, stopping the node");
+            break Outcome::Completed;
+        }
+
+        match rx.recv_timeout(recovery.time_until_next_sync()) {
+            Ok(Signal::Interrupt) | Err(RecvTimeoutError::Disconnected) => {
+                break match recovery.failed_peers().is_empty() {
+                    true => Outcome::Interrupted,
+                    false => Outcome::PeerFailures,
+                };
+            }
+            Ok(Signal::Redraw) => {
+                if let Some(d) = &mut frontend.dashboard {
+                    d.redraw()?;
+                }
+            }
+            Ok(Signal::Event(event, handled)) => {
+                recovery.handle_event(&event, &mut frontend)?;
+                let _ = handled.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if recovery.poll(&mut frontend)? {
+                    let node = recovery.node().context("recovery has not been started")?;
+                    event_listener = EventListener::spawn(Arc::clone(node), tx.clone());
+                }
+            }
+        }
+    };
+    // No more events are handled, so stop taking them from the node.
+    drop(event_listener);
+
+    let snapshot = recovery.snapshot()?;
+    if frontend.dashboard.take().is_some() {
+        // The dashboard is gone from the screen once closed, so leave the
+        // final balances behind.
+        print_balances(
+            &snapshot.report,
+            &snapshot.chain_source,
+            snapshot.tip_height,
+        );
+    }
+
+    match outcome {
+        Outcome::Completed => println!("Recovery completed successfully"),
+        _ => println!("Stopping..."),
+    }
+    if recovery.has_stale_channels() {
+        println!("WARNING: channels with an outdated backup were left to their peers to close; their funds are not included. Resume the recovery with a current backup to recover them.");
+    }
+
+    if outcome == Outcome::Completed {
+        let sweep_address = args.sweep_address()?;
+        if let Some(address) = &sweep_address {
+            let node = recovery.node().context("recovery has not been started")?;
+            match sweep_funds(node, address, args.sweep_fee_rate) {
+                Ok(()) => {
+                    recovery.record_broadcast(true, &mut frontend)?;
+                }
+                Err(e) => {
+                    if !recovery.record_broadcast(false, &mut frontend)? {
+                        return Err(e);
+                    }
+                    warn!("sweep failed, retrying with another chain source: {:#}", e);
+                    let node = recovery.node().context("recovery has not been started")?;
+                    sweep_funds(node, address, args.sweep_fee_rate)?;
+                }
+            }
+        }
+        hooks.fire(
+            Hook::RecoveryComplete,
+            json!({
+                "spendable_sats": snapshot.report.spendable,
+                "total_sats": snapshot.report.total,
+                "sweep_address": sweep_address.map(|a| a.to_string()),
+            }),
         );
     }
 
-    let node = Arc::new(builder.build().context("failed to instantiate LDK node")?);
+    recovery.stop()?;
+    info!("done");
+
+    Ok(outcome)
+}
 
-    node.start().context("failed to start LDK node")?;
+fn describe_channel_state(state: Option<ChannelState>) -> &'static str {
+    match state {
+        Some(ChannelState::Pending) => "waiting for peer connection",
+        Some(ChannelState::ForceCloseInitiated) => "force close requested",
+        Some(ChannelState::StaleBackup) => "backup outdated, left to the peer to close",
+        None => "unknown",
+    }
+}
 
-    println!("Synchronizing wallets...");
-    if let Err(e) = node.sync_wallets() {
-        error!("failed to perform initial wallet synchronization: {:?}", e);
-        eprintln!("Failed to synchronize wallets: {:#}", e);
+fn describe_age(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    match now.saturating_sub(timestamp) {
+        s if s < 120 => format!("{} seconds ago", s),
+        s if s < 7200 => format!("{} minutes ago", s / 60),
+        s => format!("{} hours ago", s / 3600),
     }
+}
 
-    let mut connected_peers = HashSet::new();
-    let mut failed_peers = HashSet::new();
+/// Shows the progress of the recovery. Builds the node on the recovery data, so
+/// it cannot run beside a recovery in progress.
+fn status(args: &mut Args, local_dir: &Path, data_dir: &DataDir) -> Result<()> {
+    let mnemonic = get_mnemonic(args)?;
+    let _lock = data_dir.lock_recovery_shared(&scb::derive_node_id(&mnemonic))?;
+    let scb = load_backup(args, local_dir, &mnemonic)?;
+    let config = args.recovery_config()?;
+    let dir = &recovery::open_recovery_dir(data_dir, &mnemonic, &scb, &mut Console)?;
 
-    println!("Found {} channel(s) in backup.", scb.channels.len());
-    if scb.channels.is_empty() {
-        return Err(anyhow!("this channel backup does not have any channels"));
+    let state = recovery::load_state(dir)?;
+    if state.is_empty() {
+        println!("No recovery in progress.");
+        return Ok(());
     }
 
-    // NOTE: simply connecting to peers with channel monitors but an empty channel manager
-    // will request channels to be force closed
-    println!("Connecting to peers...");
+    println!("Channels:");
     for ch in &scb.channels {
-        if connected_peers.contains(&ch.peer_id) {
-            continue;
+        println!(
+            "  {} with node {}: {}",
+            ch.channel_id,
+            ch.peer_id,
+            describe_channel_state(state.get_channel_state(&ch.peer_id, &ch.channel_id))
+        );
+    }
+    println!();
+
+    // The node is not started, so there are no connections to peers or the
+    // chain source; balances are as of the last sync of a recovery run.
+    let backends = ChainBackends::new(config.chain_sources.clone(), config.proxy.clone())?;
+    let node = recovery::build_node(&config, dir, &mnemonic, backends.active(), None)?;
+    drop(mnemonic);
+    let node_status = node.status();
+    match node_status.latest_onchain_wallet_sync_timestamp {
+        Some(ts) => println!("Last wallet sync: {}", describe_age(ts)),
+        None => println!("Last wallet sync: never"),
+    }
+
+    let report = balance::collect_balances(&node, &scb.channels);
+    print_balances(
+        &report,
+        &backends.status(),
+        node_status.current_best_block.height,
+    );
+
+    if report.pending_total() == 0 && state.get_all_channel_ids() == scb.channel_ids() {
+        println!("No pending funds. Run the recovery to check for updates.");
+    }
+
+    Ok(())
+}
+
+fn report(
+    args: &mut Args,
+    local_dir: &Path,
+    data_dir: &DataDir,
+    format: ReportFormat,
+    output: Option<&Path>,
+) -> Result<()> {
+    // Like the status, the report builds the node on the recovery data.
+    let mnemonic = get_mnemonic(args)?;
+    let _lock = data_dir.lock_recovery_shared(&scb::derive_node_id(&mnemonic))?;
+    let scb = load_backup(args, local_dir, &mnemonic)?;
+    let config = args.recovery_config()?;
+    // Messages would end up in the report on stdout.
+    let dir = &recovery::open_recovery_dir(data_dir, &mnemonic, &scb, &mut ())?;
+
+    if recovery::load_state(dir)?.is_empty() {
+        return Err(anyhow!("no recovery found in {}", dir.display()));
+    }
+    let journal = Journal::open(dir.join(JOURNAL_FILE)).context("failed to open journal")?;
+
+    // The node is not started; the wallet's transactions are as of the last
+    // sync of a recovery run.
+    let backends = ChainBackends::new(config.chain_sources.clone(), config.proxy.clone())?;
+    let node = recovery::build_node(&config, dir, &mnemonic, backends.active(), None)?;
+    drop(mnemonic);
+    let wallet_txids = report::inbound_txids(&node.list_payments());
+    let mut reports = report::channel_reports(&scb.channels, journal.entries());
+
+    match backends.active() {
+        ChainSource::Esplora(url) => {
+            if let Err(e) =
+                report::add_chain_data(&mut reports, &wallet_txids, url, config.proxy.as_ref())
+            {
+                warn!("failed to look up channel transactions: {:?}", e);
+                eprintln!("Could not look up the channel transactions: {:#}", e);
+            }
+            let failed = reports.iter().filter(|r| r.chain_error.is_some()).count();
+            if failed > 0 {
+                eprintln!(
+                    "Could not look up the transactions of {} channel(s); see their chain_error.",
+                    failed
+                );
+            }
+        }
+        ChainSource::Electrum(_) => {
+            eprintln!("Channel transactions can only be looked up with an Esplora server.");
+        }
+    }
+
+    let mut out: Box<dyn io::Write> = match output {
+        Some(path) => Box::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    match format {
+        ReportFormat::Csv => report::write_csv(&mut out, &reports)?,
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &reports)?;
+            writeln!(out)?;
         }
+    }
+    out.flush().context("failed to write report")?;
+
+    if let Some(path) = output {
+        println!("Report written to {}", path.display());
+    }
+    Ok(())
+}
 
-        info!("connecting to peer {} {}", ch.peer_socket_address, ch.peer_id);
+fn inspect(args: &mut Args, local_dir: &Path, data_dir: &DataDir) -> Result<()> {
+    let mnemonic = get_mnemonic(args)?;
+    let scb = load_backup(args, local_dir, &mnemonic)?;
+
+    println!(
+        "Node ID: {}",
+        scb.node_id
+            .clone()
+            .unwrap_or_else(|| scb::derive_node_id(&mnemonic).to_string())
+    );
+    println!("Channels: {}", scb.channels.len());
+    for ch in &scb.channels {
+        println!("  Channel {}", ch.channel_id);
+        println!("    Peer: {}@{}", ch.peer_id, ch.peer_socket_address);
+        println!("    Funding tx: {}", ch.funding_tx_id);
+    }
+    println!("Channel monitors: {}", scb.monitors.len());
+    for m in &scb.monitors {
+        println!("  {} ({} bytes)", m.key, m.value.len());
+    }
 
-        let pkey = PublicKey::from_str(&ch.peer_id).context(format!(
-            "bad static channel backup: invalid peer ID: {}",
-            ch.peer_id
-        ))?;
-        let peer_addr = parse_peer_address(&ch.peer_socket_address)?;
-        if let Err(e) = node.connect(pkey, peer_addr, true) {
-            error!("failed to connect to peer {}: {}", ch.peer_id, e);
-            failed_peers.insert(ch.peer_id.clone());
+    let dir = data_dir.recovery_dir(&scb::derive_node_id(&mnemonic));
+    let state = recovery::load_state(&dir)?;
+    if !state.is_empty() {
+        if state.get_all_channel_ids() == scb.channel_ids() {
+            println!("A recovery from this backup is in progress.");
         } else {
-            info!(
-                "connected to peer {} {}",
-                ch.peer_socket_address, ch.peer_id
-            );
-            println!("     connected");
-            connected_peers.insert(ch.peer_id.clone());
+            println!("A recovery from a different backup is in progress.");
         }
     }
 
-    if !failed_peers.is_empty() {
-        println!("Failed to connect to the following peers:");
-        for peer in failed_peers {
-            println!("  {}", peer);
+    Ok(())
+}
+
+fn withdraw(
+    args: &mut Args,
+    data_dir: &DataDir,
+    address: Option<&Address<NetworkUnchecked>>,
+    fee_rate: Option<u64>,
+    force: bool,
+    yes: bool,
+) -> Result<()> {
+    // Checked before the wallets are synced, which can take a while.
+    if args.non_interactive && !yes {
+        return Err(missing_input("withdrawal must be confirmed with --yes"));
+    }
+
+    let address = match address {
+        Some(a) => a
+            .clone()
+            .require_network(args.network())
+            .context("address is not valid for the selected network")?,
+        None => args
+            .sweep_address()?
+            .ok_or_else(|| missing_input("no withdrawal address provided"))?,
+    };
+
+    let mnemonic = get_mnemonic(args)?;
+    let node_id = scb::derive_node_id(&mnemonic);
+    let _lock = data_dir.lock_recovery(&node_id)?;
+
+    let dir = &data_dir.recovery_dir(&node_id);
+    if !dir.join(LDK_DIR).try_exists().unwrap_or(false) {
+        return Err(anyhow!("no recovery data found in {}", dir.display()));
+    }
+
+    let config = args.recovery_config()?;
+    let mut backends = ChainBackends::new(config.chain_sources.clone(), config.proxy.clone())?;
+    let chain_source = backends.select_healthy().clone();
+    println!("Using {}", chain_source);
+
+    let node = recovery::build_node(&config, dir, &mnemonic, &chain_source, None)?;
+    drop(mnemonic);
+    node.start().context("failed to start LDK node")?;
+
+    println!("Synchronizing wallets...");
+    let result = node
+        .sync_wallets()
+        .context("failed to synchronize wallets")
+        .and_then(|_| {
+            let pending = balance::collect_balances(&node, &[]).pending_total();
+            if pending > 0 {
+                println!(
+                    "{} sats of closed channels are still pending; claiming them may need on-chain funds.",
+                    pending
+                );
+                if !force {
+                    println!("Wait for the recovery to complete, or pass --force to withdraw anyway.");
+                    return Err(anyhow!("funds of closed channels are still pending"));
+                }
+            }
+            let spendable = node.list_balances().spendable_onchain_balance_sats;
+            if !yes && spendable > 0 {
+                let answer = prompt(&format!(
+                    "Send {} sats (minus fees) to {}? Type YES to confirm:",
+                    spendable, address
+                ));
+                if answer != "YES" {
+                    println!("Withdrawal cancelled.");
+                    return Ok(());
+                }
+            }
+            sweep_funds(&node, &address, fee_rate.or(args.sweep_fee_rate))
+        });
+
+    node.stop().context("failed to stop LDK node")?;
+    result
+}
+
+fn reset(
+    args: &mut Args,
+    data_dir: &DataDir,
+    node_id: Option<PublicKey>,
+    yes: bool,
+    force: bool,
+) -> Result<()> {
+    let (node_id, mnemonic) = match node_id {
+        Some(id) => (id, None),
+        None => {
+            let mnemonic = get_mnemonic(args)?;
+            (scb::derive_node_id(&mnemonic), Some(mnemonic))
         }
-        println!("Please check the logs for details.");
+    };
+    let lock = data_dir.lock_recovery(&node_id)?;
+    let dir = &data_dir.recovery_dir(&node_id);
+
+    let paths = data_dir.recovery_data(&node_id);
+    if paths.is_empty() {
+        println!("No recovery state found in {}.", dir.display());
+        return Ok(());
     }
 
-    // For all newly connected peers, update their channels' state.
-    for ch in scb.channels.iter() {
-        if connected_peers.contains(&ch.peer_id)
-            && state
-                .get_channel_state(&ch.peer_id, &ch.channel_id)
-                .unwrap_or(ChannelState::Pending)
-                == ChannelState::Pending
-        {
-            state.set_channel_state(
-                &ch.peer_id,
-                &ch.channel_id,
-                ChannelState::ForceCloseInitiated,
+    // The node is not started; balances are as of the last sync.
+    let funds = match &mnemonic {
+        Some(mnemonic) if dir.join(LDK_DIR).try_exists().unwrap_or(false) => {
+            let config = args.recovery_config()?;
+            let backends = ChainBackends::new(config.chain_sources.clone(), config.proxy.clone())?;
+            let node = recovery::build_node(&config, dir, mnemonic, backends.active(), None)?;
+            Some(balance::collect_balances(&node, &[]))
+        }
+        _ => None,
+    };
+    let checked = mnemonic.is_some();
+    drop(mnemonic);
+
+    match &funds {
+        Some(report) if report.total + report.reserved + report.pending_total() > 0 => {
+            println!(
+                "The recovery still holds funds: {} sats on-chain and {} sats in closed channels.",
+                report.total + report.reserved,
+                report.pending_total()
             );
+            if !force {
+                println!("Withdraw the funds first, or pass --force to reset anyway.");
+                return Err(anyhow!("the recovery still holds funds"));
+            }
+        }
+        Some(_) => {}
+        // Without LDK data, the node never held any funds.
+        None if checked => {}
+        None => {
+            println!("The balances cannot be checked without the seed phrase.");
+            if !force {
+                println!(
+                    "Make sure all funds have been withdrawn, then pass --force to reset anyway."
+                );
+                return Err(anyhow!("the balances of the recovery cannot be checked"));
+            }
         }
     }
 
-    state
-        .save(dir.join(STATE_FILE))
-        .context("failed to save recovery state")?;
+    println!("The following will be moved to the archive:");
+    for p in &paths {
+        println!("  {}", p.display());
+    }
+    println!("WARNING: the recovery process will start from scratch.");
 
-    println!("Waiting for channel recovery to complete. This may take a while...");
-    println!("It is safe to interrupt this program by pressing Ctrl-C. You can resume it later to check recovery status.");
-    let (tx, rx) = mpsc::channel();
-    ctrlc::set_handler(move || tx.send(()).expect("Could not send signal on channel."))
-        .expect("Error setting Ctrl-C handler");
+    if !yes {
+        if args.non_interactive {
+            return Err(missing_input("reset must be confirmed with --yes"));
+        }
+        if prompt("Type RESET to confirm:") != "RESET" {
+            println!("Reset cancelled.");
+            return Ok(());
+        }
+    }
 
-    let mut last_balance = Instant::now();
-    let mut last_sync = Instant::now();
-    loop {
-        if rx.try_recv().is_ok() {
-            println!("Stopping...");
-            break;
+    let archive = data_dir
+        .archive_recovery(&node_id, &lock)
+        .inspect_err(|_| {
+            eprintln!("To reset the recovery state manually, move the following elsewhere:");
+            for p in &paths {
+                eprintln!("  {}", p.display());
+            }
+        })?;
+
+    println!(
+        "Recovery state has been reset. The previous data is in {}.",
+        archive.display()
+    );
+    Ok(())
+}
+
+fn list(data_dir: &DataDir) -> Result<()> {
+    let recoveries = data_dir.list()?;
+    if recoveries.is_empty() {
+        println!("No recoveries found in {}.", data_dir.root().display());
+    } else {
+        println!("Recoveries in {}:", data_dir.root().display());
+    }
+
+    for r in &recoveries {
+        println!("  Node {}", r.node_id);
+        match &r.state {
+            Err(e) => println!("    Unreadable: {:#}", e),
+            Ok(Some(state)) if !state.is_empty() => {
+                let channels = state.get_all_channel_ids().len();
+                match state.has_pending_channels() {
+                    true => println!(
+                        "    {} channel(s), some still waiting for peer connection",
+                        channels
+                    ),
+                    false => println!("    {} channel(s), force close requested", channels),
+                }
+                if state.has_stale_channels() {
+                    println!("    Some channels have an outdated backup and are left to their peers to close");
+                }
+            }
+            _ => println!("    Not started"),
+        }
+        if let Some(updated) = r.updated {
+            let ts = updated
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            println!("    Last updated {}", describe_age(ts));
+        }
+        println!("    {}", r.path.display());
+    }
+
+    let legacy_paths = data_dir.legacy_paths();
+    if !legacy_paths.is_empty() {
+        println!("Recovery data from an older version (moved to its node directory when the recovery is resumed):");
+        for p in &legacy_paths {
+            println!("  {}", p.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends all spendable on-chain funds to the given address. While funds of
+/// closed channels are pending, the reserve needed to claim them is kept.
+fn sweep_funds(node: &Node, address: &Address, fee_rate: Option<u64>) -> Result<()> {
+    let fee_rate = fee_rate
+        .map(|r| FeeRate::from_sat_per_vb(r).ok_or(anyhow!("invalid sweep fee rate: {}", r)))
+        .transpose()?;
+
+    let spendable = node.list_balances().spendable_onchain_balance_sats;
+    if spendable == 0 {
+        println!("No spendable funds to sweep");
+        return Ok(());
+    }
+
+    let retain_reserves = balance::collect_balances(node, &[]).pending_total() > 0;
+    info!("sweeping {} sats to {}", spendable, address);
+    let txid = node
+        .onchain_payment()
+        .send_all_to_address(address, retain_reserves, fee_rate)
+        .context("failed to sweep funds")?;
+    info!("sweep transaction broadcast: {}", txid);
+    println!(
+        "Sent {} sats (minus fees) to {} in transaction {}",
+        spendable, address, txid
+    );
+
+    Ok(())
+}
+
+/// Input to the main loop.
+enum Signal {
+    Interrupt,
+    Redraw,
+    /// An LDK event, to be confirmed on the sender once handled.
+    Event(Box<Event>, mpsc::Sender<()>),
+}
+
+/// Thread passing the events of a node to the main loop. It is stopped and
+/// joined when dropped, so that it does not keep a replaced node alive.
+struct EventListener {
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl EventListener {
+    fn spawn(node: Arc<Node>, tx: mpsc::Sender<Signal>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        // Events are polled rather than waited for, so that the thread can
+        // notice when it is to stop.
+        let handle = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                let Some(event) = node.next_event() else {
+                    thread::sleep(EVENT_POLL_INTERVAL);
+                    continue;
+                };
+                let (handled_tx, handled) = mpsc::channel();
+                if tx.send(Signal::Event(Box::new(event), handled_tx)).is_err() {
+                    break;
+                }
+                // The node keeps an event until it is marked as handled, also
+                // across restarts, so that is only done once the main loop
+                // has handled it.
+                loop {
+                    match handled.recv_timeout(EVENT_POLL_INTERVAL) {
+                        Ok(()) => {
+                            if let Err(e) = node.event_handled() {
+                                error!("failed to mark event as handled: {:?}", e);
+                            }
+                            break;
+                        }
+                        Err(RecvTimeoutError::Timeout) if !thread_stop.load(Ordering::Relaxed) => {}
+                        Err(_) => return,
+                    }
+                }
+            }
+        });
+        Self {
+            stop,
+            handle: Some(handle),
         }
+    }
+}
 
-        let now = Instant::now();
+impl Drop for EventListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
 
-        if now.duration_since(last_balance).as_secs() >= 3 {
-            if balance::check_and_print_balances(&node, &scb.channels) == 0 {
-                info!("no more pending funds, stopping the node");
-                println!("Recovery completed successfully");
+/// Forwards dashboard key presses and resizes to the main loop.
+fn spawn_input_listener(tx: mpsc::Sender<Signal>) {
+    thread::spawn(move || {
+        while let Some(input) = tui::read_input() {
+            let signal = match input {
+                tui::Input::Quit => Signal::Interrupt,
+                tui::Input::Resize => Signal::Redraw,
+            };
+            if tx.send(signal).is_err() {
                 break;
             }
-            last_balance = now;
         }
+    });
+}
 
-        if now.duration_since(last_sync).as_secs() >= 4 {
-            info!("syncing wallets");
-            if let Err(e) = node.sync_wallets() {
-                error!("failed to sync wallets: {:?}", e);
-            } else {
-                info!("wallets synced");
+/// Prints the progress of commands other than the recovery itself.
+struct Console;
+
+impl Observer for Console {
+    fn message(&mut self, msg: &str) -> Result<()> {
+        println!("{}", msg);
+        Ok(())
+    }
+
+    fn warning(&mut self, msg: &str) -> Result<()> {
+        println!("WARNING: {}", msg);
+        Ok(())
+    }
+}
+
+/// Shows the progress of the recovery in the dashboard or as printed lines,
+/// and passes it on to the hooks and the API.
+struct Frontend<'a> {
+    hooks: &'a Hooks,
+    metrics: Arc<Metrics>,
+    has_proxy: bool,
+    allow_direct_peers: bool,
+    api: Option<ApiServer>,
+    dashboard: Option<tui::Dashboard>,
+    last_printed: Option<(BalanceReport, String)>,
+    last_channels: Option<Vec<progress::ChannelRow>>,
+}
+
+impl<'a> Frontend<'a> {
+    fn new(hooks: &'a Hooks, metrics: Arc<Metrics>, config: &RecoveryConfig) -> Self {
+        Self {
+            hooks,
+            metrics,
+            has_proxy: config.proxy.is_some(),
+            allow_direct_peers: config.allow_direct_peers,
+            api: None,
+            dashboard: None,
+            last_printed: None,
+            last_channels: None,
+        }
+    }
+
+    /// Shows a progress message in the dashboard event log if the dashboard
+    /// is active, or prints it otherwise.
+    fn notify(&mut self, msg: &str) -> Result<()> {
+        match &mut self.dashboard {
+            Some(d) => d.log(msg),
+            None => {
+                println!("{}", msg);
+                Ok(())
             }
-            last_sync = now;
         }
+    }
+}
 
-        loop {
-            match node.next_event() {
-                Some(event) => {
-                    info!("event: {:?}", event);
-                    node.event_handled();
-                }
-                None => break,
+impl Observer for Frontend<'_> {
+    fn message(&mut self, msg: &str) -> Result<()> {
+        self.notify(msg)
+    }
+
+    fn warning(&mut self, msg: &str) -> Result<()> {
+        self.notify(&format!("WARNING: {}", msg))
+    }
+
+    fn peer_connected(&mut self, peer_id: &str, address: &SocketAddress) -> Result<()> {
+        self.metrics.record_connection(true);
+        self.hooks.fire(
+            Hook::PeerConnected,
+            json!({ "node_id": peer_id, "address": address.to_string() }),
+        );
+        self.notify("     connected")
+    }
+
+    fn peer_failed(
+        &mut self,
+        _peer_id: &str,
+        address: Option<&SocketAddress>,
+        _error: &str,
+    ) -> Result<()> {
+        self.metrics.record_connection(false);
+        if address.is_some_and(proxy::is_onion_address) && !self.has_proxy {
+            return self.notify("     peer has an onion address; restart with --proxy socks5://127.0.0.1:9050 and a running Tor daemon to connect to it");
+        }
+        if address.is_some_and(|a| !proxy::is_onion_address(a))
+            && self.has_proxy
+            && !self.allow_direct_peers
+        {
+            return self.notify("     peer has no onion address; set one in the [[peers]] section of the configuration file, or restart with --allow-direct-peers to connect to it without the proxy");
+        }
+        Ok(())
+    }
+
+    fn sync_failed(&mut self, error: &NodeError) -> Result<()> {
+        match &mut self.dashboard {
+            Some(d) => d.log(&format!("wallet sync failed: {:#}", error)),
+            None => {
+                eprintln!("Failed to synchronize wallets: {:#}", error);
+                Ok(())
             }
         }
+    }
 
-        thread::sleep(Duration::from_millis(100));
+    fn chain_source_switched(&mut self, source: &ChainSource) -> Result<()> {
+        self.notify(&format!("Switching to {}...", source))
     }
 
-    info!("stopping node");
-    node.stop().context("failed to stop LDK node")?;
-    info!("done");
+    fn event(&mut self, event: &Event) -> Result<()> {
+        if let Some(api) = &self.api {
+            api.record_event(event);
+        }
+        match &mut self.dashboard {
+            Some(d) => d.log(&progress::describe_event(event)),
+            None => Ok(()),
+        }
+    }
 
-    Ok(())
+    fn progress(&mut self, snapshot: &Snapshot, peers: &[PeerStatus]) -> Result<()> {
+        if let Some(old) = &self.last_channels {
+            self.hooks.fire_channel_milestones(old, &snapshot.channels);
+        }
+        self.last_channels = Some(snapshot.channels.clone());
+        if let Some(api) = &self.api {
+            api.update(snapshot, peers.to_vec());
+        }
+        match &mut self.dashboard {
+            Some(d) => d.update(snapshot.clone()),
+            None => {
+                // Only print the balances when they have changed.
+                let current = (snapshot.report.clone(), snapshot.chain_source.clone());
+                if self.last_printed.as_ref() != Some(&current) {
+                    print_balances(
+                        &snapshot.report,
+                        &snapshot.chain_source,
+                        snapshot.tip_height,
+                    );
+                    self.last_printed = Some(current);
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
-fn ignore_not_found(e: io::Error) -> io::Result<()> {
-    match e.kind() {
-        io::ErrorKind::NotFound => Ok(()),
-        _ => Err(e),
+fn print_channel_amounts(amounts: &[ChannelAmount]) {
+    for c in amounts {
+        if c.channel_id.is_none() {
+            println!("    {} sats (channel unknown)", c.amount);
+            continue;
+        }
+
+        println!(
+            "    {} sats from node {}, funding tx {}",
+            c.amount,
+            c.peer_id.as_deref().unwrap_or("<unknown>"),
+            c.funding_tx.as_deref().unwrap_or("<unknown>")
+        );
     }
 }
 
-fn reset_recovery<P: AsRef<Path>>(dir: P) -> Result<()> {
-    let dir = dir.as_ref();
-    std::fs::remove_file(dir.join(STATE_FILE))
-        .or_else(ignore_not_found)
-        .context("failed to delete recovery state file")?;
-    std::fs::remove_dir_all(dir.join(LDK_DIR))
-        .or_else(ignore_not_found)
-        .context("failed to delete LDK data directory")?;
-    Ok(())
+fn print_balances(report: &BalanceReport, chain_source: &str, tip_height: u32) {
+    info!(
+        "balances: spendable: {}, reserved: {}, claimable: {}, pending sweep: {}",
+        report.spendable,
+        report.reserved,
+        report.claimable_total(),
+        report.pending_sweep_total()
+    );
+
+    println!("Chain source: {}", chain_source);
+    println!("Balances as of block {} (sats):", tip_height);
+    println!(
+        "  Spendable: {}; total: {}; reserved: {}",
+        report.spendable, report.total, report.reserved
+    );
+    println!(
+        "  Pending from channel closures: {}",
+        report.pending_total()
+    );
+
+    if !report.claimable.is_empty() {
+        println!("  Claimable:");
+        print_channel_amounts(&report.claimable);
+    }
+
+    if !report.pending_sweep.is_empty() {
+        println!("  Pending sweep:");
+        print_channel_amounts(&report.pending_sweep);
+    }
+
+    println!();
 }
 
 fn get_own_dir() -> Result<PathBuf> {
@@ -415,36 +1492,103 @@ fn get_local_dir(use_cwd: bool) -> Result<PathBuf> {
     }
 }
 
-fn main() {
-    let args = Args::parse();
-
-    setup_logging(args.verbosity).unwrap();
+fn main() -> ExitCode {
+    let Cli { command, mut args } = Cli::parse();
+    // Clap has read the seed phrase; hooks and the other child processes
+    // must not inherit it. No other thread is running yet.
+    args.seed_from_env = std::env::var_os(SEED_ENV_VAR).is_some();
+    std::env::remove_var(SEED_ENV_VAR);
 
     let local_dir = match get_local_dir(args.use_workdir) {
         Ok(d) => d,
         Err(e) => {
-            error!("failed to get own directory: {:?}", e);
-            return;
+            eprintln!("Failed to get own directory: {:#}", e);
+            return failure::exit_code(&e);
         }
     };
 
-    if args.reset_recovery {
-        if let Err(e) = reset_recovery(&local_dir) {
-            error!("failed to reset recovery state: {:?}", e);
-            eprintln!("Failed to reset recovery state: {:#}", e);
-            eprintln!("To reset the recovery state manually, delete the following:");
-            eprintln!("  {}", STATE_FILE);
-            eprintln!("  {}", LDK_DIR);
-            return;
-        }
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| local_dir.join(CONFIG_FILE));
+    let config = match ConfigFile::try_load(&config_path) {
+        Ok(None) if args.config.is_some() => Err(anyhow!("config file not found")),
+        r => r,
+    };
+    if let Err(e) = config.and_then(|c| c.map_or(Ok(()), |c| args.apply_config(c))) {
+        eprintln!("Invalid configuration ({}): {:#}", config_path.display(), e);
+        return failure::exit_code(&e);
+    }
+    if let Err(e) = args.validate() {
+        eprintln!("Invalid options: {:#}", e);
+        return failure::exit_code(&e);
     }
 
-    if let Err(e) = run(&args, &local_dir) {
-        error!("recovery failed: {:?}", e);
-
+    let data_dir = DataDir::new(args.data_dir.clone().unwrap_or_else(|| local_dir.clone()));
+    if let Err(e) = std::fs::create_dir_all(data_dir.root()) {
         eprintln!(
-            "Recovery failed; error: {:#} (see the {} file for details)",
-            e, LOG_FILE
+            "Failed to create data directory {}: {}",
+            data_dir.root().display(),
+            e
         );
+        return ExitCode::from(failure::EXIT_ERROR);
+    }
+
+    if let Err(e) = logging::setup(
+        &data_dir.root().join(LOG_FILE),
+        args.verbosity,
+        args.log_format.unwrap_or_default(),
+        args.log_stderr,
+        &args.log_config,
+    ) {
+        eprintln!("Failed to set up logging: {:#}", e);
+        return failure::exit_code(&e);
+    }
+    debug!("options: {:?}", args);
+
+    if let Some(proxy) = &args.proxy {
+        proxy::set_env_proxy(proxy);
+    }
+
+    let hooks = Hooks::new(args.hooks.clone());
+    let result = match command.unwrap_or(Command::Recover) {
+        Command::Recover => recover(&mut args, &local_dir, &data_dir, &hooks).inspect_err(|e| {
+            hooks.fire(Hook::Error, json!({ "error": format!("{:#}", e) }));
+        }),
+        Command::Status => status(&mut args, &local_dir, &data_dir).map(|_| Outcome::Completed),
+        Command::Inspect => inspect(&mut args, &local_dir, &data_dir).map(|_| Outcome::Completed),
+        Command::Withdraw {
+            address,
+            fee_rate,
+            force,
+            yes,
+        } => withdraw(&mut args, &data_dir, address.as_ref(), fee_rate, force, yes)
+            .map(|_| Outcome::Completed),
+        Command::Reset {
+            node_id,
+            yes,
+            force,
+        } => reset(&mut args, &data_dir, node_id, yes, force).map(|_| Outcome::Completed),
+        Command::List => list(&data_dir).map(|_| Outcome::Completed),
+        Command::Report { format, output } => {
+            report(&mut args, &local_dir, &data_dir, format, output.as_deref())
+                .map(|_| Outcome::Completed)
+        }
+    };
+
+    hooks.finish();
+
+    match result {
+        Ok(outcome) => outcome.exit_code(),
+        Err(e) => {
+            error!("command failed: {:?}", e);
+
+            eprintln!(
+                "Error: {:#} (see the {} file for details)",
+                e,
+                data_dir.root().join(LOG_FILE).display()
+            );
+            failure::exit_code(&e)
+        }
     }
 }
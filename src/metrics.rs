@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ldk_node::Event;
+use prometheus::{
+    Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::balance::ChannelAmount;
+use crate::progress::{self, Snapshot};
+
+/// Prometheus metrics of a running recovery.
+pub struct Metrics {
+    registry: Registry,
+    spendable_sats: IntGauge,
+    total_sats: IntGauge,
+    reserved_sats: IntGauge,
+    claimable_sats: IntGaugeVec,
+    pending_sweep_sats: IntGaugeVec,
+    best_block_height: IntGauge,
+    seconds_since_last_sync: IntGauge,
+    last_sync: Mutex<Option<u64>>,
+    peer_connection_attempts: IntCounter,
+    peer_connection_failures: IntCounter,
+    events: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("hub_recovery".to_string()), None)
+            .expect("valid registry prefix");
+
+        let gauge = |name: &str, help: &str| {
+            let g = IntGauge::new(name, help).expect("valid metric");
+            registry
+                .register(Box::new(g.clone()))
+                .expect("unique metric");
+            g
+        };
+        let channel_gauge = |name: &str, help: &str| {
+            let g = IntGaugeVec::new(Opts::new(name, help), &["channel_id", "peer_id"])
+                .expect("valid metric");
+            registry
+                .register(Box::new(g.clone()))
+                .expect("unique metric");
+            g
+        };
+        let counter = |name: &str, help: &str| {
+            let c = IntCounter::new(name, help).expect("valid metric");
+            registry
+                .register(Box::new(c.clone()))
+                .expect("unique metric");
+            c
+        };
+
+        let events = IntCounterVec::new(
+            Opts::new("events_total", "LDK events received, by type"),
+            &["type"],
+        )
+        .expect("valid metric");
+        registry
+            .register(Box::new(events.clone()))
+            .expect("unique metric");
+
+        Self {
+            spendable_sats: gauge("onchain_spendable_sats", "Spendable on-chain balance"),
+            total_sats: gauge("onchain_total_sats", "Total on-chain balance"),
+            reserved_sats: gauge(
+                "onchain_reserved_sats",
+                "On-chain balance reserved for anchor channels",
+            ),
+            claimable_sats: channel_gauge(
+                "channel_claimable_sats",
+                "Funds claimable from a closed channel",
+            ),
+            pending_sweep_sats: channel_gauge(
+                "channel_pending_sweep_sats",
+                "Funds from a closed channel waiting for the sweep to confirm",
+            ),
+            best_block_height: gauge("best_block_height", "Height of the best known block"),
+            seconds_since_last_sync: gauge(
+                "seconds_since_last_sync",
+                "Seconds since the last successful wallet sync",
+            ),
+            last_sync: Mutex::new(None),
+            peer_connection_attempts: counter(
+                "peer_connection_attempts_total",
+                "Attempts to connect to channel peers",
+            ),
+            peer_connection_failures: counter(
+                "peer_connection_failures_total",
+                "Failed attempts to connect to channel peers",
+            ),
+            events,
+            registry,
+        }
+    }
+
+    pub fn observe(&self, snapshot: &Snapshot) {
+        let report = &snapshot.report;
+        self.spendable_sats.set(report.spendable as i64);
+        self.total_sats.set(report.total as i64);
+        self.reserved_sats.set(report.reserved as i64);
+        set_channel_amounts(&self.claimable_sats, &report.claimable);
+        set_channel_amounts(&self.pending_sweep_sats, &report.pending_sweep);
+        self.best_block_height.set(snapshot.tip_height.into());
+        *self.last_sync.lock().unwrap() = snapshot.last_sync;
+    }
+
+    pub fn record_connection(&self, ok: bool) {
+        self.peer_connection_attempts.inc();
+        if !ok {
+            self.peer_connection_failures.inc();
+        }
+    }
+
+    pub fn record_event(&self, event: &Event) {
+        self.events
+            .with_label_values(&[event_type(event).as_str()])
+            .inc();
+    }
+
+    /// Renders the metrics in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        // Set on scrape so that the value keeps growing while syncs fail.
+        let age = match *self.last_sync.lock().unwrap() {
+            Some(t) => progress::now().saturating_sub(t) as i64,
+            None => -1,
+        };
+        self.seconds_since_last_sync.set(age);
+
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("metrics encode to a buffer");
+        String::from_utf8(buf).expect("metrics are valid UTF-8")
+    }
+}
+
+/// Replaces the per-channel values, so that channels without funds drop out.
+fn set_channel_amounts(gauge: &IntGaugeVec, amounts: &[ChannelAmount]) {
+    let mut by_channel: HashMap<(&str, &str), u64> = HashMap::new();
+    for c in amounts {
+        let key = (
+            c.channel_id.as_deref().unwrap_or("unknown"),
+            c.peer_id.as_deref().unwrap_or("unknown"),
+        );
+        *by_channel.entry(key).or_default() += c.amount;
+    }
+
+    gauge.reset();
+    for ((channel_id, peer_id), amount) in by_channel {
+        gauge
+            .with_label_values(&[channel_id, peer_id])
+            .set(amount as i64);
+    }
+}
+
+/// Name of the event variant, taken from its debug representation so that
+/// new event types are counted without changes here.
+fn event_type(event: &Event) -> String {
+    let debug = format!("{:?}", event);
+    debug
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
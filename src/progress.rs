@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ldk_node::{Event, Node};
+use serde::Serialize;
+
+use crate::balance::{BalanceReport, ChannelAmount};
+use crate::scb::ChannelBackup;
+use crate::state::{ChannelState, State};
+
+/// Recovery stage of a channel, as far as it can be told from the balances.
+/// The stages are ordered by progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum ChannelStatus {
+    /// The backup is outdated, so only the peer can close the channel.
+    StaleBackup,
+    WaitingForPeer,
+    /// The closing transaction is not confirmed yet.
+    Closing,
+    /// The channel is closed on-chain and the funds wait for a timelock.
+    Timelocked,
+    /// A sweep transaction waits to be broadcast or confirmed.
+    SweepConfirming,
+    /// The sweep has confirmed and waits for enough confirmations.
+    SweepFinalizing,
+    NoFundsPending,
+}
+
+impl ChannelStatus {
+    /// Whether the channel's closing transaction has confirmed.
+    pub fn is_closed_on_chain(self) -> bool {
+        !matches!(
+            self,
+            ChannelStatus::StaleBackup | ChannelStatus::WaitingForPeer | ChannelStatus::Closing
+        )
+    }
+}
+
+impl fmt::Display for ChannelStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChannelStatus::StaleBackup => "stale backup",
+            ChannelStatus::WaitingForPeer => "waiting for peer",
+            ChannelStatus::Closing => "closing",
+            ChannelStatus::Timelocked => "timelocked",
+            ChannelStatus::SweepConfirming => "sweep confirming",
+            ChannelStatus::SweepFinalizing => "sweep finalizing",
+            ChannelStatus::NoFundsPending => "no funds pending",
+        })
+    }
+}
+
+/// Recovery progress of a single channel from the backup.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ChannelRow {
+    pub channel_id: String,
+    pub peer_id: String,
+    pub status: ChannelStatus,
+    pub amount: u64,
+    pub unlock_height: Option<u32>,
+}
+
+/// Recovery progress as reported by the recovery engine.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct Snapshot {
+    pub channels: Vec<ChannelRow>,
+    pub report: BalanceReport,
+    pub tip_height: u32,
+    pub last_sync: Option<u64>,
+    pub chain_source: String,
+}
+
+/// Builds the per-channel rows from the recovery state and balances.
+pub fn channel_rows(
+    channels: &[ChannelBackup],
+    state: &State,
+    report: &BalanceReport,
+) -> Vec<ChannelRow> {
+    channels
+        .iter()
+        .map(|ch| {
+            let of_channel =
+                |c: &&ChannelAmount| c.channel_id.as_deref() == Some(ch.channel_id.as_str());
+            let claimable: Vec<_> = report.claimable.iter().filter(of_channel).collect();
+            let sweeping: Vec<_> = report.pending_sweep.iter().filter(of_channel).collect();
+
+            // Some balances, e.g. of HTLCs, have no unlock height even once
+            // the closing transaction has confirmed.
+            let status = if sweeping.iter().any(|c| c.awaiting_confirmation) {
+                ChannelStatus::SweepConfirming
+            } else if !sweeping.is_empty() {
+                ChannelStatus::SweepFinalizing
+            } else if claimable.iter().any(|c| c.awaiting_close) {
+                ChannelStatus::Closing
+            } else if !claimable.is_empty() {
+                ChannelStatus::Timelocked
+            } else {
+                match state.get_channel_state(&ch.peer_id, &ch.channel_id) {
+                    Some(ChannelState::ForceCloseInitiated) => ChannelStatus::NoFundsPending,
+                    Some(ChannelState::StaleBackup) => ChannelStatus::StaleBackup,
+                    _ => ChannelStatus::WaitingForPeer,
+                }
+            };
+
+            let amounts = claimable.iter().chain(sweeping.iter());
+            ChannelRow {
+                channel_id: ch.channel_id.clone(),
+                peer_id: ch.peer_id.clone(),
+                status,
+                amount: amounts.clone().map(|c| c.amount).sum(),
+                unlock_height: amounts.filter_map(|c| c.unlock_height).max(),
+            }
+        })
+        .collect()
+}
+
+/// Connection status of a peer from the backup.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct PeerStatus {
+    pub node_id: String,
+    pub address: String,
+    pub connected: bool,
+}
+
+/// Lists the peers of the channels in the backup and whether the node is
+/// currently connected to them.
+pub fn peer_statuses(node: &Node, channels: &[ChannelBackup]) -> Vec<PeerStatus> {
+    let connected: HashSet<String> = node
+        .list_peers()
+        .into_iter()
+        .filter(|p| p.is_connected)
+        .map(|p| p.node_id.to_string())
+        .collect();
+
+    let mut seen = HashSet::new();
+    channels
+        .iter()
+        .filter(|ch| seen.insert(ch.peer_id.as_str()))
+        .map(|ch| PeerStatus {
+            node_id: ch.peer_id.clone(),
+            address: ch.peer_socket_address.clone(),
+            connected: connected.contains(&ch.peer_id),
+        })
+        .collect()
+}
+
+/// Describes an LDK event for event logs.
+pub fn describe_event(event: &Event) -> String {
+    match event {
+        Event::ChannelClosed {
+            channel_id, reason, ..
+        } => match reason {
+            Some(reason) => format!("channel {} closed: {}", channel_id, reason),
+            None => format!("channel {} closed", channel_id),
+        },
+        _ => format!("{:?}", event),
+    }
+}
+
+/// Current UNIX time in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(id: &str) -> ChannelBackup {
+        ChannelBackup {
+            channel_id: id.to_string(),
+            peer_id: "peer".to_string(),
+            peer_socket_address: "127.0.0.1:9735".to_string(),
+            funding_tx_id: format!("funding-{}", id),
+        }
+    }
+
+    fn amount(channel_id: &str, unlock_height: Option<u32>, awaiting_close: bool) -> ChannelAmount {
+        ChannelAmount {
+            channel_id: Some(channel_id.to_string()),
+            peer_id: Some("peer".to_string()),
+            funding_tx: None,
+            amount: 1_000,
+            unlock_height,
+            awaiting_confirmation: false,
+            awaiting_close,
+        }
+    }
+
+    #[test]
+    fn test_channel_rows() {
+        let channels: Vec<_> = ["a", "b", "c", "d", "e", "f"].map(channel).into();
+        let mut state = State::new();
+        state.set_channel_state("peer", "e", ChannelState::ForceCloseInitiated);
+        state.set_channel_state("peer", "f", ChannelState::StaleBackup);
+        let report = BalanceReport {
+            spendable: 0,
+            total: 0,
+            reserved: 0,
+            claimable: vec![
+                amount("a", None, true),
+                // An HTLC of a channel whose closing transaction has
+                // confirmed.
+                amount("b", None, false),
+                amount("b", Some(120), false),
+            ],
+            pending_sweep: vec![
+                ChannelAmount {
+                    awaiting_confirmation: true,
+                    ..amount("c", None, false)
+                },
+                amount("d", Some(130), false),
+            ],
+        };
+
+        let rows = channel_rows(&channels, &state, &report);
+        let statuses: Vec<_> = rows.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ChannelStatus::Closing,
+                ChannelStatus::Timelocked,
+                ChannelStatus::SweepConfirming,
+                ChannelStatus::SweepFinalizing,
+                ChannelStatus::NoFundsPending,
+                ChannelStatus::StaleBackup,
+            ]
+        );
+        assert_eq!((rows[1].amount, rows[1].unlock_height), (2_000, Some(120)));
+    }
+}
@@ -0,0 +1,885 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use ldk_node::bip39::Mnemonic;
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::bitcoin::Network;
+use ldk_node::config::{Config, TorConfig};
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use ldk_node::{Event, KeyValue, Node, NodeError};
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::balance::{self, BalanceReport};
+use crate::chain::{ChainBackends, ChainSource};
+use crate::datadir::{self, DataDir, JOURNAL_FILE, LDK_DIR, STATE_FILE};
+use crate::failure::Failure;
+use crate::journal::{Journal, JournalEvent};
+use crate::progress::{self, PeerStatus, Snapshot};
+use crate::proxy;
+use crate::scb::{self, ChannelBackup, EncodedChannelMonitorBackup, StaticChannelBackup};
+use crate::schedule::{Activity, SyncSchedule};
+use crate::state::{ChannelState, State};
+
+/// Settings of a recovery that do not come from the backup.
+#[derive(Debug, Clone)]
+pub struct RecoveryConfig {
+    pub network: Network,
+    /// Chain sources in order of preference.
+    pub chain_sources: Vec<ChainSource>,
+    /// SOCKS5 proxy for connections to peers and chain sources.
+    pub proxy: Option<Url>,
+    /// Whether peers without an onion address may be connected to directly,
+    /// bypassing the proxy. Such peers are not connected to otherwise.
+    pub allow_direct_peers: bool,
+    /// Addresses that override the ones in the backup, by peer node ID.
+    pub peer_addresses: HashMap<String, String>,
+    pub ldk_config: Config,
+    pub sync_interval_min: Duration,
+    pub sync_interval_max: Duration,
+}
+
+/// Receives the progress of a [`Recovery`]. All methods do nothing by
+/// default; an error returned by any of them aborts the current step.
+pub trait Observer {
+    /// A step of the recovery worth showing to the user.
+    fn message(&mut self, _msg: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// A problem that needs the user's attention.
+    fn warning(&mut self, _msg: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn peer_connected(&mut self, _peer_id: &str, _address: &SocketAddress) -> Result<()> {
+        Ok(())
+    }
+
+    /// The peer could not be connected to. `address` is missing if it could
+    /// not be determined.
+    fn peer_failed(
+        &mut self,
+        _peer_id: &str,
+        _address: Option<&SocketAddress>,
+        _error: &str,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_failed(&mut self, _error: &NodeError) -> Result<()> {
+        Ok(())
+    }
+
+    /// The node is about to be restarted with another chain source after
+    /// repeated sync failures.
+    fn chain_source_switched(&mut self, _source: &ChainSource) -> Result<()> {
+        Ok(())
+    }
+
+    fn event(&mut self, _event: &Event) -> Result<()> {
+        Ok(())
+    }
+
+    fn progress(&mut self, _snapshot: &Snapshot, _peers: &[PeerStatus]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Observer that ignores all progress.
+impl Observer for () {}
+
+/// The running node and what was last seen of it.
+struct Session {
+    node: Arc<Node>,
+    tip_height: u32,
+    report: BalanceReport,
+}
+
+/// A recovery of the channels in a static channel backup.
+///
+/// The recovery is driven by the caller: [`Recovery::start`] starts the node,
+/// [`Recovery::connect_peers`] has the peers force close the channels, and
+/// [`Recovery::poll`] and [`Recovery::handle_event`] keep track of the funds
+/// until [`Recovery::is_complete`]. Its state is kept in the data directory,
+/// so a recovery can be stopped and resumed at any time.
+pub struct Recovery {
+    config: RecoveryConfig,
+    dir: PathBuf,
+    scb: StaticChannelBackup,
+    state: State,
+    journal: Journal,
+    resumed: bool,
+    backends: ChainBackends,
+    /// Only kept when the node may have to be rebuilt with another chain
+    /// source.
+    mnemonic: Option<Mnemonic>,
+    failed_peers: HashSet<String>,
+    schedule: SyncSchedule,
+    session: Option<Session>,
+}
+
+impl Recovery {
+    /// Opens the recovery of the backup in the data directory, initializing
+    /// its state unless a recovery is in progress.
+    ///
+    /// The backup must belong to the seed phrase. Fails with
+    /// [`Failure::BackupChanged`] if the recovery in progress was started
+    /// from a different backup.
+    pub fn new(
+        config: RecoveryConfig,
+        data_dir: &DataDir,
+        mnemonic: Mnemonic,
+        scb: StaticChannelBackup,
+        observer: &mut dyn Observer,
+    ) -> Result<Self> {
+        if scb.channels.is_empty() {
+            return Err(anyhow!("this channel backup does not have any channels"))
+                .context(Failure::BadBackup);
+        }
+
+        let dir = open_recovery_dir(data_dir, &mnemonic, &scb, observer)?;
+        info!("using recovery directory {}", dir.display());
+
+        let mut state = load_state(&dir)?;
+        let resumed = !state.is_empty();
+        if !resumed {
+            info!("initializing recovery state");
+            // Only a backup older than one used before in the recovery is
+            // detected, see `check_monitor_updates`.
+            observer.warning("make sure this is the latest channel backup. The channels are closed from it, and closing a channel from an outdated backup forfeits its funds to the peer.")?;
+            scb.channels.iter().for_each(|ch| {
+                state.set_channel_state(&ch.peer_id, &ch.channel_id, ChannelState::Pending);
+            });
+            state
+                .save(dir.join(STATE_FILE))
+                .context("failed to save recovery state")?;
+        } else if state.get_all_channel_ids() != scb.channel_ids() {
+            // If the channels in SCB don't match channels in the recovery state
+            // file, it is likely that the recovery process has been restarted
+            // with a different static channel backup file. We do not allow that.
+            error!("static channel backup file has changed; cannot proceed with the recovery");
+            return Err(anyhow!(
+                "static channel backup file does not match the stored state"
+            ))
+            .context(Failure::BackupChanged);
+        }
+
+        let journal = Journal::open(dir.join(JOURNAL_FILE)).context("failed to open journal")?;
+        let backends = ChainBackends::new(config.chain_sources.clone(), config.proxy.clone())?;
+        let schedule = SyncSchedule::new(config.sync_interval_min, config.sync_interval_max);
+
+        Ok(Self {
+            config,
+            dir,
+            scb,
+            state,
+            journal,
+            resumed,
+            backends,
+            mnemonic: Some(mnemonic),
+            failed_peers: HashSet::new(),
+            schedule,
+            session: None,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn channels(&self) -> &[ChannelBackup] {
+        &self.scb.channels
+    }
+
+    /// Whether the recovery was already in progress when it was opened.
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    pub fn has_stale_channels(&self) -> bool {
+        self.state.has_stale_channels()
+    }
+
+    /// Peers that could not be connected to.
+    pub fn failed_peers(&self) -> &HashSet<String> {
+        &self.failed_peers
+    }
+
+    /// The running node, once started.
+    pub fn node(&self) -> Option<&Arc<Node>> {
+        self.session.as_ref().map(|s| &s.node)
+    }
+
+    /// Whether all funds have been recovered.
+    pub fn is_complete(&self) -> bool {
+        self.session
+            .as_ref()
+            .is_some_and(|s| s.report.pending_total() == 0)
+    }
+
+    pub fn time_until_next_sync(&self) -> Duration {
+        self.schedule.time_until_next()
+    }
+
+    /// Restores the channel monitors from the backup, then starts the node
+    /// and synchronizes its wallets.
+    pub fn start(&mut self, observer: &mut dyn Observer) -> Result<()> {
+        let mnemonic = self
+            .mnemonic
+            .take()
+            .context("recovery has already been started")?;
+
+        if self.backends.has_alternatives() {
+            observer.message("Checking chain sources...")?;
+        }
+        let chain_source = self.backends.select_healthy().clone();
+        observer.message(&format!("Using {}", chain_source))?;
+
+        // Monitors are restored whenever they are missing from the store, e.g.
+        // if an earlier run crashed before they were persisted or the LDK data was
+        // deleted. Stored monitors are never replaced, as they may be newer.
+        let stored_monitors = datadir::stored_monitors(&self.dir)?;
+        let stale_monitors =
+            check_monitor_updates(&self.scb, &stored_monitors, &mut self.state, observer)?;
+        self.save_state()?;
+        let monitors: Vec<KeyValue> = std::mem::take(&mut self.scb.monitors)
+            .into_iter()
+            .filter(|m| !stored_monitors.contains_key(&m.key) && !stale_monitors.contains(&m.key))
+            .map(EncodedChannelMonitorBackup::into)
+            .collect();
+        // Caught here, as the node would fail to start on them.
+        for m in &monitors {
+            scb::read_monitor(&m.value)
+                .with_context(|| format!("cannot read channel monitor {} in the backup", m.key))
+                .context(Failure::BadBackup)?;
+        }
+        if !monitors.is_empty() {
+            info!(
+                "restoring {} channel monitor(s) from backup",
+                monitors.len()
+            );
+            if self.resumed {
+                observer.message(&format!(
+                    "Restoring {} channel monitor(s) missing from the recovery data.",
+                    monitors.len()
+                ))?;
+            }
+        }
+        let monitors = (!monitors.is_empty()).then_some(monitors);
+
+        let node = build_node(&self.config, &self.dir, &mnemonic, &chain_source, monitors)?;
+        node.start().context("failed to start LDK node")?;
+
+        // The seed phrase is only kept to rebuild the node when switching to
+        // another chain source; otherwise it is wiped now.
+        self.mnemonic = self.backends.has_alternatives().then_some(mnemonic);
+
+        observer.message("Synchronizing wallets...")?;
+        let sync_result = node.sync_wallets();
+        if let Err(e) = &sync_result {
+            error!("failed to perform initial wallet synchronization: {:?}", e);
+            observer.sync_failed(e)?;
+        }
+        self.backends.record_sync(sync_result.is_ok());
+
+        let session = Session {
+            tip_height: node.status().current_best_block.height,
+            report: balance::collect_balances(&node, &self.scb.channels),
+            node,
+        };
+        self.session = Some(session);
+        Ok(())
+    }
+
+    /// Connects to the peers of the channels in the backup.
+    ///
+    /// Connecting to a peer with channel monitors but an empty channel manager
+    /// makes the node request the channels to be force closed.
+    pub fn connect_peers(&mut self, observer: &mut dyn Observer) -> Result<()> {
+        let node = Arc::clone(&self.session()?.node);
+        let tip_height = self.session()?.tip_height;
+        let mut connected_peers = HashSet::new();
+
+        observer.message("Connecting to peers...")?;
+        for ch in &self.scb.channels {
+            if connected_peers.contains(&ch.peer_id) || self.failed_peers.contains(&ch.peer_id) {
+                continue;
+            }
+
+            info!(
+                "connecting to peer {} {}",
+                ch.peer_socket_address, ch.peer_id
+            );
+
+            let pkey = PublicKey::from_str(&ch.peer_id)
+                .context(format!(
+                    "bad static channel backup: invalid peer ID: {}",
+                    ch.peer_id
+                ))
+                .context(Failure::BadBackup)?;
+            let peer_addr = match self.config.peer_addresses.get(&ch.peer_id) {
+                Some(addr) => {
+                    info!("using address {} for peer {} from config", addr, ch.peer_id);
+                    SocketAddress::from_str(addr).map_err(|e| {
+                        anyhow!(
+                            "invalid address {} for peer {} in config: {:?}",
+                            addr,
+                            ch.peer_id,
+                            e
+                        )
+                    })?
+                }
+                None => {
+                    scb::parse_peer_address(&ch.peer_socket_address).context(Failure::BadBackup)?
+                }
+            };
+            if proxy::is_onion_address(&peer_addr) && self.config.proxy.is_none() {
+                error!(
+                    "cannot connect to peer {} at onion address {} without a proxy",
+                    ch.peer_id, ch.peer_socket_address
+                );
+                observer.peer_failed(
+                    &ch.peer_id,
+                    Some(&peer_addr),
+                    "onion addresses require a proxy",
+                )?;
+                self.failed_peers.insert(ch.peer_id.clone());
+                continue;
+            }
+            if !proxy::is_onion_address(&peer_addr) && self.config.proxy.is_some() {
+                // The node only uses the proxy for onion addresses, so the
+                // connection would reveal the IP address.
+                if !self.config.allow_direct_peers {
+                    error!(
+                        "not connecting to peer {} at {}: direct connections are not allowed with a proxy",
+                        ch.peer_id, peer_addr
+                    );
+                    observer.peer_failed(
+                        &ch.peer_id,
+                        Some(&peer_addr),
+                        "not an onion address, and direct connections are not allowed with a proxy",
+                    )?;
+                    self.failed_peers.insert(ch.peer_id.clone());
+                    continue;
+                }
+                warn!(
+                    "connecting to peer {} at {} directly, not through the proxy",
+                    ch.peer_id, peer_addr
+                );
+                observer.warning(&format!(
+                    "connecting to peer {} at {} directly, not through the proxy.",
+                    ch.peer_id, peer_addr
+                ))?;
+            }
+
+            if let Err(e) = node.connect(pkey, peer_addr.clone(), true) {
+                error!("failed to connect to peer {}: {}", ch.peer_id, e);
+                observer.peer_failed(&ch.peer_id, Some(&peer_addr), &e.to_string())?;
+                self.failed_peers.insert(ch.peer_id.clone());
+            } else {
+                info!(
+                    "connected to peer {} {}",
+                    ch.peer_socket_address, ch.peer_id
+                );
+                observer.peer_connected(&ch.peer_id, &peer_addr)?;
+                connected_peers.insert(ch.peer_id.clone());
+            }
+        }
+
+        // For all newly connected peers, update their channels' state.
+        for ch in self.scb.channels.iter() {
+            if connected_peers.contains(&ch.peer_id)
+                && self
+                    .state
+                    .get_channel_state(&ch.peer_id, &ch.channel_id)
+                    .unwrap_or(ChannelState::Pending)
+                    == ChannelState::Pending
+            {
+                self.state.set_channel_state(
+                    &ch.peer_id,
+                    &ch.channel_id,
+                    ChannelState::ForceCloseInitiated,
+                );
+                self.journal.record(
+                    tip_height,
+                    JournalEvent::ForceCloseRequested {
+                        channel_id: ch.channel_id.clone(),
+                        peer_id: ch.peer_id.clone(),
+                    },
+                )?;
+            }
+        }
+
+        self.save_state()
+    }
+
+    /// Current progress of the recovery.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let session = self.session()?;
+        Ok(Snapshot {
+            channels: progress::channel_rows(&self.scb.channels, &self.state, &session.report),
+            report: session.report.clone(),
+            tip_height: session.tip_height,
+            last_sync: session.node.status().latest_onchain_wallet_sync_timestamp,
+            chain_source: self.backends.status(),
+        })
+    }
+
+    /// Connection status of the peers from the backup.
+    pub fn peer_statuses(&self) -> Result<Vec<PeerStatus>> {
+        Ok(progress::peer_statuses(
+            &self.session()?.node,
+            &self.scb.channels,
+        ))
+    }
+
+    /// Reports the current progress to the observer.
+    pub fn report_progress(&self, observer: &mut dyn Observer) -> Result<()> {
+        observer.progress(&self.snapshot()?, &self.peer_statuses()?)
+    }
+
+    /// Updates the balances after an event of the node.
+    pub fn handle_event(&mut self, event: &Event, observer: &mut dyn Observer) -> Result<()> {
+        info!("event: {:?}", event);
+        observer.event(event)?;
+
+        if let Event::ChannelClosed {
+            channel_id, reason, ..
+        } = event
+        {
+            let tip_height = self.session()?.tip_height;
+            self.journal.record(
+                tip_height,
+                JournalEvent::ChannelClosed {
+                    channel_id: hex::encode(channel_id.0),
+                    reason: reason.as_ref().map(|r| r.to_string()),
+                },
+            )?;
+        }
+
+        // Events like channel closures usually mean that new
+        // transactions are about to show up on chain.
+        self.schedule.speed_up();
+        let session = self
+            .session
+            .as_mut()
+            .context("recovery has not been started")?;
+        session.report = balance::collect_balances(&session.node, &self.scb.channels);
+        self.report_progress(observer)
+    }
+
+    /// Synchronizes the wallets if a sync is due, switching to another chain
+    /// source if the current one keeps failing.
+    ///
+    /// Returns whether the node was restarted, in which case events must be
+    /// taken from the new node.
+    pub fn poll(&mut self, observer: &mut dyn Observer) -> Result<bool> {
+        let Some(node) = self.sync_due()? else {
+            return Ok(false);
+        };
+        info!("syncing wallets");
+        let sync_result = node.sync_wallets();
+        self.finish_sync(sync_result, observer)
+    }
+
+    /// Returns the node whose wallets to synchronize if a sync is due. Lets
+    /// the caller synchronize without holding on to the recovery; pass the
+    /// result to [`Recovery::finish_sync`].
+    pub fn sync_due(&self) -> Result<Option<Arc<Node>>> {
+        if !self.schedule.is_due() {
+            return Ok(None);
+        }
+        Ok(Some(Arc::clone(&self.session()?.node)))
+    }
+
+    /// Updates the recovery with the result of a wallet sync, see
+    /// [`Recovery::poll`].
+    pub fn finish_sync(
+        &mut self,
+        sync_result: Result<(), NodeError>,
+        observer: &mut dyn Observer,
+    ) -> Result<bool> {
+        if self.session.is_none() {
+            // Stopped while the wallets were synchronized.
+            return Ok(false);
+        }
+        match &sync_result {
+            Ok(()) => info!("wallets synced"),
+            Err(e) => {
+                error!("failed to sync wallets: {:?}", e);
+                observer.sync_failed(e)?;
+            }
+        }
+
+        let mut restarted = false;
+        if let Some(chain_source) = self.backends.record_sync(sync_result.is_ok()).cloned() {
+            self.switch_chain_source(&chain_source, observer)?;
+            restarted = true;
+        }
+
+        let session = self
+            .session
+            .as_mut()
+            .context("recovery has not been started")?;
+        let new_tip_height = session.node.status().current_best_block.height;
+        let new_report = balance::collect_balances(&session.node, &self.scb.channels);
+        let activity = if sync_result.is_err()
+            || new_tip_height != session.tip_height
+            || new_report != session.report
+        {
+            Activity::Changed
+        } else if new_report.expects_confirmation(new_tip_height) {
+            Activity::ExpectingConfirmation
+        } else {
+            Activity::Idle
+        };
+        self.schedule.schedule_next(activity);
+        debug!(
+            "sync activity: {:?}, next sync in {:?}",
+            activity,
+            self.schedule.interval()
+        );
+
+        session.tip_height = new_tip_height;
+        session.report = new_report;
+        self.report_progress(observer)?;
+        Ok(restarted)
+    }
+
+    /// Records the result of broadcasting a transaction through the node, and
+    /// switches to another chain source if the broadcast failed and a healthy
+    /// one exists. Returns whether the node was restarted, in which case the
+    /// broadcast can be retried. The node's own broadcasts are not covered, as
+    /// it does not report their failures.
+    pub fn record_broadcast(&mut self, ok: bool, observer: &mut dyn Observer) -> Result<bool> {
+        match self.backends.record_broadcast(ok).cloned() {
+            Some(chain_source) => {
+                self.switch_chain_source(&chain_source, observer)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Restarts the node with another chain source.
+    fn switch_chain_source(
+        &mut self,
+        chain_source: &ChainSource,
+        observer: &mut dyn Observer,
+    ) -> Result<()> {
+        observer.chain_source_switched(chain_source)?;
+        info!("restarting node with chain source {}", chain_source);
+        let session = self
+            .session
+            .as_mut()
+            .context("recovery has not been started")?;
+        session.node.stop().context("failed to stop LDK node")?;
+        let mnemonic = self
+            .mnemonic
+            .as_ref()
+            .context("seed phrase not available")?;
+        session.node = build_node(&self.config, &self.dir, mnemonic, chain_source, None)?;
+        session.node.start().context("failed to start LDK node")?;
+        Ok(())
+    }
+
+    /// Stops the node. The recovery can be resumed later.
+    pub fn stop(&mut self) -> Result<()> {
+        if let Some(session) = self.session.take() {
+            info!("stopping node");
+            session.node.stop().context("failed to stop LDK node")?;
+        }
+        Ok(())
+    }
+
+    fn session(&self) -> Result<&Session> {
+        self.session
+            .as_ref()
+            .context("recovery has not been started")
+    }
+
+    fn save_state(&self) -> Result<()> {
+        self.state
+            .save(self.dir.join(STATE_FILE))
+            .context("failed to save recovery state")
+    }
+}
+
+pub fn build_node(
+    config: &RecoveryConfig,
+    dir: &Path,
+    mnemonic: &Mnemonic,
+    chain_source: &ChainSource,
+    monitors: Option<Vec<KeyValue>>,
+) -> Result<Arc<Node>> {
+    let mut builder = ldk_node::Builder::from_config(config.ldk_config.clone());
+    builder
+        .set_entropy_bip39_mnemonic(mnemonic.clone(), None)
+        .set_network(config.network)
+        .set_storage_dir_path(
+            dir.join(LDK_DIR)
+                .to_str()
+                .ok_or(anyhow!("invalid LDK path"))?
+                .to_string(),
+        )
+        .set_log_facade_logger();
+
+    info!("using chain source: {}", chain_source);
+    chain_source.configure(&mut builder);
+
+    if let Some(proxy) = &config.proxy {
+        info!(
+            "connecting to onion peers through proxy {}",
+            proxy::redacted(proxy)
+        );
+        builder
+            .set_tor_config(TorConfig {
+                proxy_address: proxy::socket_address(proxy)?,
+            })
+            .context("failed to configure proxy")?;
+    }
+
+    if let Some(monitors) = monitors {
+        builder.restore_encoded_channel_monitors(monitors);
+    }
+
+    Ok(Arc::new(
+        builder.build().context("failed to instantiate LDK node")?,
+    ))
+}
+
+/// Returns the directory of the recovery for the given seed, moving data in
+/// the legacy layout into it if it belongs to the backup.
+pub fn open_recovery_dir(
+    data_dir: &DataDir,
+    mnemonic: &Mnemonic,
+    scb: &StaticChannelBackup,
+    observer: &mut dyn Observer,
+) -> Result<PathBuf> {
+    let node_id = scb::derive_node_id(mnemonic);
+    let dir = data_dir.recovery_dir(&node_id);
+
+    let legacy_paths = data_dir.legacy_paths();
+    if !legacy_paths.is_empty() && !dir.try_exists().unwrap_or(false) {
+        let legacy_state = data_dir.load_legacy_state()?;
+        if legacy_state.is_some_and(|s| s.get_all_channel_ids() == scb.channel_ids()) {
+            info!("moving legacy recovery data to {}", dir.display());
+            data_dir.migrate_legacy(&dir)?;
+            observer.message(&format!(
+                "Moved the existing recovery data to {}",
+                dir.display()
+            ))?;
+        } else {
+            warn!(
+                "legacy recovery data in {} does not match the backup, leaving it in place",
+                data_dir.root().display()
+            );
+            let mut msg =
+                "Found recovery data that does not belong to this backup; leaving it in place:"
+                    .to_string();
+            for p in &legacy_paths {
+                msg.push_str(&format!("\n  {}", p.display()));
+            }
+            observer.message(&msg)?;
+        }
+    }
+
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create recovery directory {}", dir.display()))?;
+    Ok(dir)
+}
+
+pub fn load_state(dir: &Path) -> Result<State> {
+    Ok(State::try_load(dir.join(STATE_FILE))
+        .context("failed to load recovery state")?
+        .unwrap_or_default())
+}
+
+/// Compares the update IDs of the backed up channel monitors with the monitors
+/// seen before, records the highest IDs and returns the keys of the outdated
+/// monitors that would have to be restored.
+///
+/// An outdated monitor may hold a commitment transaction that the peer has
+/// already revoked. If the node broadcast it, the peer could claim all funds
+/// of the channel, so these monitors are not restored and the channels are
+/// left to the peers to close.
+///
+/// A backup older than the channel's latest state is only detected if a newer
+/// monitor was seen before, not on the first run of a recovery.
+fn check_monitor_updates(
+    scb: &StaticChannelBackup,
+    stored_monitors: &HashMap<String, Option<u64>>,
+    state: &mut State,
+    observer: &mut dyn Observer,
+) -> Result<HashSet<String>> {
+    let mut stale = HashSet::new();
+    for m in &scb.monitors {
+        let backup_id = m.update_id();
+        let known_id = stored_monitors
+            .get(&m.key)
+            .copied()
+            .flatten()
+            .max(state.monitor_update_id(&m.key));
+
+        if let Some(id) = backup_id.max(known_id) {
+            state.set_monitor_update_id(&m.key, id);
+        }
+
+        let (Some(backup_id), Some(known_id)) = (backup_id, known_id) else {
+            continue;
+        };
+        if backup_id >= known_id {
+            // A channel left to the peer because of an outdated backup is
+            // recovered again once the recovery is resumed with a current one.
+            for ch in scb.channels_of_monitor(&m.key) {
+                if state.get_channel_state(&ch.peer_id, &ch.channel_id)
+                    == Some(ChannelState::StaleBackup)
+                {
+                    info!(
+                        "backup of channel {} is current again, recovering it",
+                        ch.channel_id
+                    );
+                    state.set_channel_state(&ch.peer_id, &ch.channel_id, ChannelState::Pending);
+                }
+            }
+            continue;
+        }
+
+        if stored_monitors.contains_key(&m.key) {
+            info!(
+                "backup of channel monitor {} is outdated (update {} < {}), using the stored monitor",
+                m.key, backup_id, known_id
+            );
+            continue;
+        }
+
+        warn!(
+            "backup of channel monitor {} is outdated (update {} < {}), not restoring it",
+            m.key, backup_id, known_id
+        );
+        for ch in scb.channels_of_monitor(&m.key) {
+            observer.warning(&format!(
+                "the backup of channel {} is outdated. Closing the channel from this backup could forfeit its funds to the peer, so the channel is left to peer {} to close.",
+                ch.channel_id, ch.peer_id
+            ))?;
+            state.set_channel_state(&ch.peer_id, &ch.channel_id, ChannelState::StaleBackup);
+        }
+        stale.insert(m.key.clone());
+    }
+    Ok(stale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: &str = "peer";
+
+    fn monitor(key: &str, update_id: Option<u64>) -> EncodedChannelMonitorBackup {
+        let value = match update_id {
+            Some(id) => [vec![1, 1], id.to_be_bytes().to_vec()].concat(),
+            None => vec![1],
+        };
+        EncodedChannelMonitorBackup {
+            key: key.to_string(),
+            value,
+        }
+    }
+
+    /// A backup with one channel for each monitor, and the state of a new
+    /// recovery of it.
+    fn backup(monitors: Vec<EncodedChannelMonitorBackup>) -> (StaticChannelBackup, State) {
+        let channels: Vec<_> = monitors
+            .iter()
+            .map(|m| ChannelBackup {
+                channel_id: format!("channel-{}", m.key),
+                peer_id: PEER.to_string(),
+                peer_socket_address: "127.0.0.1:9735".to_string(),
+                funding_tx_id: m.key.split('_').next().unwrap().to_string(),
+            })
+            .collect();
+        let mut state = State::new();
+        for ch in &channels {
+            state.set_channel_state(&ch.peer_id, &ch.channel_id, ChannelState::Pending);
+        }
+        let scb = StaticChannelBackup {
+            node_id: None,
+            channels,
+            monitors,
+        };
+        (scb, state)
+    }
+
+    fn channel_state(state: &State, key: &str) -> Option<ChannelState> {
+        state.get_channel_state(PEER, &format!("channel-{}", key))
+    }
+
+    #[test]
+    fn test_current_backup() {
+        let (scb, mut state) = backup(vec![monitor("a_0", Some(5)), monitor("b_0", Some(8))]);
+        state.set_monitor_update_id("a_0", 5);
+        let stored = HashMap::from([("b_0".to_string(), Some(3))]);
+
+        let stale = check_monitor_updates(&scb, &stored, &mut state, &mut ()).unwrap();
+        assert!(stale.is_empty());
+        assert_eq!(state.monitor_update_id("a_0"), Some(5));
+        assert_eq!(state.monitor_update_id("b_0"), Some(8));
+        assert_eq!(channel_state(&state, "a_0"), Some(ChannelState::Pending));
+    }
+
+    #[test]
+    fn test_stale_backup() {
+        let (scb, mut state) = backup(vec![monitor("a_0", Some(5))]);
+        state.set_monitor_update_id("a_0", 7);
+
+        let stale = check_monitor_updates(&scb, &HashMap::new(), &mut state, &mut ()).unwrap();
+        assert_eq!(stale, HashSet::from(["a_0".to_string()]));
+        assert_eq!(state.monitor_update_id("a_0"), Some(7));
+        assert_eq!(
+            channel_state(&state, "a_0"),
+            Some(ChannelState::StaleBackup)
+        );
+    }
+
+    #[test]
+    fn test_stale_backup_of_stored_monitor() {
+        // The newer stored monitor is used instead of the backup.
+        let (scb, mut state) = backup(vec![monitor("a_0", Some(5))]);
+        let stored = HashMap::from([("a_0".to_string(), Some(7))]);
+
+        let stale = check_monitor_updates(&scb, &stored, &mut state, &mut ()).unwrap();
+        assert!(stale.is_empty());
+        assert_eq!(state.monitor_update_id("a_0"), Some(7));
+        assert_eq!(channel_state(&state, "a_0"), Some(ChannelState::Pending));
+    }
+
+    #[test]
+    fn test_missing_update_ids() {
+        let (scb, mut state) = backup(vec![monitor("a_0", None), monitor("b_0", Some(5))]);
+        state.set_monitor_update_id("a_0", 7);
+        let stored = HashMap::from([("b_0".to_string(), None)]);
+
+        let stale = check_monitor_updates(&scb, &stored, &mut state, &mut ()).unwrap();
+        assert!(stale.is_empty());
+        assert_eq!(state.monitor_update_id("a_0"), Some(7));
+        assert_eq!(state.monitor_update_id("b_0"), Some(5));
+        assert_eq!(channel_state(&state, "a_0"), Some(ChannelState::Pending));
+        assert_eq!(channel_state(&state, "b_0"), Some(ChannelState::Pending));
+    }
+
+    #[test]
+    fn test_stale_channel_recovered_with_current_backup() {
+        let (scb, mut state) = backup(vec![monitor("a_0", Some(7))]);
+        state.set_monitor_update_id("a_0", 7);
+        state.set_channel_state(PEER, "channel-a_0", ChannelState::StaleBackup);
+
+        let stale = check_monitor_updates(&scb, &HashMap::new(), &mut state, &mut ()).unwrap();
+        assert!(stale.is_empty());
+        assert_eq!(channel_state(&state, "a_0"), Some(ChannelState::Pending));
+    }
+}
@@ -0,0 +1,461 @@
+//! Per-channel report of a recovery, e.g. for bookkeeping, built from the
+//! recovery journal and the channels' transactions on chain.
+
+use std::collections::{BTreeSet, HashSet};
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ldk_node::payment::{PaymentDetails, PaymentDirection, PaymentKind};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::chain;
+use crate::journal::{JournalEntry, JournalEvent};
+use crate::scb::ChannelBackup;
+
+const ESPLORA_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What happened to a channel from the backup during the recovery. Amounts
+/// are in sats, timestamps in seconds since the UNIX epoch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelReport {
+    pub channel_id: String,
+    pub peer_id: String,
+    pub funding_tx: String,
+    /// When the peer was asked to force close the channel.
+    pub force_close_requested_at: Option<u64>,
+    pub force_close_requested_height: Option<u32>,
+    /// When the node saw the channel closed.
+    pub closed_at: Option<u64>,
+    pub closed_height: Option<u32>,
+    /// The commitment transaction that closed the channel on chain, if it
+    /// could be looked up.
+    pub closing_tx: Option<String>,
+    pub closing_tx_height: Option<u32>,
+    pub closing_tx_time: Option<u64>,
+    /// Confirmed transactions that swept the channel's funds to the on-chain
+    /// wallet.
+    pub sweep_txids: Vec<String>,
+    pub last_sweep_height: Option<u32>,
+    pub last_sweep_time: Option<u64>,
+    /// Value of the swept outputs, before fees.
+    pub amount_recovered: u64,
+    /// Fees of the sweep transactions. A transaction sweeping other outputs
+    /// as well has its fee split by the values of its inputs.
+    pub fees_paid: u64,
+    /// Why the channel's transactions could not be looked up, if they could
+    /// not.
+    pub chain_error: Option<String>,
+}
+
+/// Transactions the on-chain wallet received funds from, which include the
+/// sweeps of channel funds.
+pub fn inbound_txids(payments: &[PaymentDetails]) -> HashSet<String> {
+    payments
+        .iter()
+        .filter(|p| p.direction == PaymentDirection::Inbound)
+        .filter_map(|p| match &p.kind {
+            PaymentKind::Onchain { txid, .. } => Some(txid.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Builds the report of each channel in the backup from the journal. The
+/// transactions are added by [`add_chain_data`].
+pub fn channel_reports(channels: &[ChannelBackup], journal: &[JournalEntry]) -> Vec<ChannelReport> {
+    channels
+        .iter()
+        .map(|ch| {
+            let mut report = ChannelReport {
+                channel_id: ch.channel_id.clone(),
+                peer_id: ch.peer_id.clone(),
+                funding_tx: ch.funding_tx_id.clone(),
+                force_close_requested_at: None,
+                force_close_requested_height: None,
+                closed_at: None,
+                closed_height: None,
+                closing_tx: None,
+                closing_tx_height: None,
+                closing_tx_time: None,
+                sweep_txids: Vec::new(),
+                last_sweep_height: None,
+                last_sweep_time: None,
+                amount_recovered: 0,
+                fees_paid: 0,
+                chain_error: None,
+            };
+
+            // The first time counts if a step was retried.
+            for e in journal {
+                match &e.event {
+                    JournalEvent::ForceCloseRequested { channel_id, .. }
+                        if *channel_id == ch.channel_id
+                            && report.force_close_requested_at.is_none() =>
+                    {
+                        report.force_close_requested_at = Some(e.timestamp);
+                        report.force_close_requested_height = Some(e.height);
+                    }
+                    JournalEvent::ChannelClosed { channel_id, .. }
+                        if *channel_id == ch.channel_id && report.closed_at.is_none() =>
+                    {
+                        report.closed_at = Some(e.timestamp);
+                        report.closed_height = Some(e.height);
+                    }
+                    _ => {}
+                }
+            }
+
+            report
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct EsploraTx {
+    vin: Vec<EsploraInput>,
+    vout: Vec<EsploraOutput>,
+    fee: u64,
+    status: EsploraStatus,
+}
+
+#[derive(Deserialize)]
+struct EsploraInput {
+    txid: String,
+    /// Missing for coinbase inputs.
+    prevout: Option<EsploraOutput>,
+}
+
+#[derive(Deserialize)]
+struct EsploraOutput {
+    scriptpubkey_type: String,
+    value: u64,
+}
+
+#[derive(Deserialize)]
+struct EsploraOutspend {
+    spent: bool,
+    txid: Option<String>,
+    status: Option<EsploraStatus>,
+}
+
+#[derive(Deserialize)]
+struct EsploraStatus {
+    block_height: Option<u32>,
+    block_time: Option<u64>,
+}
+
+/// A confirmed transaction sweeping outputs of a channel to the on-chain
+/// wallet.
+#[derive(Debug, PartialEq)]
+struct Sweep {
+    height: u32,
+    time: u64,
+    /// Value of the swept outputs of the channel.
+    amount: u64,
+    /// The channel's share of the fee.
+    fee: u64,
+}
+
+impl Sweep {
+    /// The sweep of the outputs of `parents`, the channel's transactions, by
+    /// `tx`, unless it has not confirmed.
+    fn new(tx: &EsploraTx, parents: &[String]) -> Option<Self> {
+        let (Some(height), Some(time)) = (tx.status.block_height, tx.status.block_time) else {
+            return None;
+        };
+        let value = |i: &EsploraInput| i.prevout.as_ref().map_or(0, |o| o.value);
+        let total: u64 = tx.vin.iter().map(value).sum();
+        let amount: u64 = tx
+            .vin
+            .iter()
+            .filter(|i| parents.contains(&i.txid))
+            .map(value)
+            .sum();
+        Some(Self {
+            height,
+            time,
+            amount,
+            fee: (tx.fee as u128 * amount as u128 / total.max(1) as u128) as u64,
+        })
+    }
+}
+
+struct Esplora {
+    client: reqwest::blocking::Client,
+    base: String,
+}
+
+impl Esplora {
+    fn get<T: DeserializeOwned>(&self, path: &str, what: &str) -> Result<T> {
+        let body = self
+            .client
+            .get(format!("{}{}", self.base, path))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.text())
+            .with_context(|| format!("failed to look up {}", what))?;
+        serde_json::from_str(&body).with_context(|| format!("unexpected response for {}", what))
+    }
+
+    fn tx(&self, txid: &str) -> Result<EsploraTx> {
+        self.get(&format!("/tx/{}", txid), &format!("transaction {}", txid))
+    }
+
+    /// The transactions spending outputs of a transaction.
+    fn spenders(&self, txid: &str) -> Result<BTreeSet<String>> {
+        let outspends: Vec<EsploraOutspend> = self.get(
+            &format!("/tx/{}/outspends", txid),
+            &format!("spends of transaction {}", txid),
+        )?;
+        Ok(outspends.into_iter().filter_map(|o| o.txid).collect())
+    }
+
+    /// Looks up the transaction spending the funding output of the channel.
+    fn add_closing_tx(&self, report: &mut ChannelReport) -> Result<()> {
+        let funding = &report.funding_tx;
+        let vout = match funding_output_index(&report.channel_id, funding) {
+            Some(vout) => vout,
+            // The channel ID is not derived from the funding outpoint, e.g. of
+            // a dual-funded channel, so take the only P2WSH output.
+            None => {
+                let tx = self.tx(funding)?;
+                let mut outputs = tx
+                    .vout
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, o)| o.scriptpubkey_type == "v0_p2wsh");
+                match (outputs.next(), outputs.next()) {
+                    (Some((vout, _)), None) => vout as u32,
+                    _ => {
+                        warn!(
+                            "cannot tell the funding output of channel {}",
+                            report.channel_id
+                        );
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        let outspend: EsploraOutspend = self.get(
+            &format!("/tx/{}/outspend/{}", funding, vout),
+            &format!("funding output {}:{}", funding, vout),
+        )?;
+        if let (true, Some(txid)) = (outspend.spent, outspend.txid) {
+            let status = outspend.status;
+            report.closing_tx_height = status.as_ref().and_then(|s| s.block_height);
+            report.closing_tx_time = status.as_ref().and_then(|s| s.block_time);
+            report.closing_tx = Some(txid);
+        }
+        Ok(())
+    }
+
+    /// Looks up the wallet transactions spending outputs of the closing
+    /// transaction, or of the node's HTLC transactions spending it.
+    fn add_sweeps(&self, report: &mut ChannelReport, wallet_txids: &HashSet<String>) -> Result<()> {
+        let Some(closing) = report.closing_tx.clone() else {
+            return Ok(());
+        };
+
+        let mut parents = vec![closing.clone()];
+        let mut sweep_txids = BTreeSet::new();
+        for spender in self.spenders(&closing)? {
+            if wallet_txids.contains(&spender) {
+                sweep_txids.insert(spender);
+                continue;
+            }
+            // An HTLC transaction, whose output is swept once it unlocks, or a
+            // claim of the peer, whose outputs are not the wallet's.
+            let swept: Vec<String> = self
+                .spenders(&spender)?
+                .into_iter()
+                .filter(|txid| wallet_txids.contains(txid))
+                .collect();
+            if !swept.is_empty() {
+                parents.push(spender);
+                sweep_txids.extend(swept);
+            }
+        }
+
+        let mut sweeps = Vec::new();
+        for txid in sweep_txids {
+            if let Some(sweep) = Sweep::new(&self.tx(&txid)?, &parents) {
+                sweeps.push((txid, sweep));
+            }
+        }
+        sweeps.sort_by_key(|(txid, sweep)| (sweep.height, txid.clone()));
+        for (txid, sweep) in sweeps {
+            report.sweep_txids.push(txid);
+            report.last_sweep_height = Some(sweep.height);
+            report.last_sweep_time = Some(sweep.time);
+            report.amount_recovered += sweep.amount;
+            report.fees_paid += sweep.fee;
+        }
+        Ok(())
+    }
+}
+
+/// Index of the funding output of a channel whose ID is derived from its
+/// funding outpoint: the funding txid, in its internal byte order, with the
+/// output index XORed into the last two bytes.
+fn funding_output_index(channel_id: &str, funding_txid: &str) -> Option<u32> {
+    let channel_id = hex::decode(channel_id).ok()?;
+    let mut txid = hex::decode(funding_txid).ok()?;
+    txid.reverse();
+    if channel_id.len() != 32 || txid.len() != 32 || channel_id[..30] != txid[..30] {
+        return None;
+    }
+    let index = u16::from_be_bytes([channel_id[30] ^ txid[30], channel_id[31] ^ txid[31]]);
+    Some(index.into())
+}
+
+/// Looks up the channels' transactions on an Esplora server: the closing
+/// transaction, which spends the funding output, and the confirmed sweeps of
+/// its outputs to the on-chain wallet, given by `wallet_txids`. A failed
+/// lookup is recorded in the channel's `chain_error` and does not stop the
+/// lookups of the other channels.
+pub fn add_chain_data(
+    reports: &mut [ChannelReport],
+    wallet_txids: &HashSet<String>,
+    esplora_url: &Url,
+    proxy: Option<&Url>,
+) -> Result<()> {
+    let esplora = Esplora {
+        client: chain::http_client(proxy, ESPLORA_TIMEOUT)?,
+        base: esplora_url.as_str().trim_end_matches('/').to_string(),
+    };
+    for report in reports.iter_mut() {
+        let result = esplora
+            .add_closing_tx(report)
+            .and_then(|()| esplora.add_sweeps(report, wallet_txids));
+        if let Err(e) = result {
+            warn!(
+                "failed to look up the transactions of channel {}: {:?}",
+                report.channel_id, e
+            );
+            report.chain_error = Some(format!("{:#}", e));
+        }
+    }
+    Ok(())
+}
+
+fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Writes the reports as CSV, one row per channel. Sweep transactions are
+/// separated by spaces.
+pub fn write_csv<W: Write>(w: W, reports: &[ChannelReport]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(w);
+    writer.write_record([
+        "channel_id",
+        "peer_id",
+        "funding_tx",
+        "force_close_requested_at",
+        "force_close_requested_height",
+        "closed_at",
+        "closed_height",
+        "closing_tx",
+        "closing_tx_height",
+        "closing_tx_time",
+        "sweep_txids",
+        "last_sweep_height",
+        "last_sweep_time",
+        "amount_recovered",
+        "fees_paid",
+        "chain_error",
+    ])?;
+    for r in reports {
+        writer.write_record([
+            r.channel_id.clone(),
+            r.peer_id.clone(),
+            r.funding_tx.clone(),
+            opt(r.force_close_requested_at),
+            opt(r.force_close_requested_height),
+            opt(r.closed_at),
+            opt(r.closed_height),
+            opt(r.closing_tx.as_ref()),
+            opt(r.closing_tx_height),
+            opt(r.closing_tx_time),
+            r.sweep_txids.join(" "),
+            opt(r.last_sweep_height),
+            opt(r.last_sweep_time),
+            r.amount_recovered.to_string(),
+            r.fees_paid.to_string(),
+            opt(r.chain_error.as_ref()),
+        ])?;
+    }
+    writer.flush().context("failed to write report")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_funding_output_index() {
+        let txid = "0102030405060708091011121314151617181920212223242526272829303132";
+        let mut channel_id = hex::decode(txid).unwrap();
+        channel_id.reverse();
+        assert_eq!(
+            funding_output_index(&hex::encode(&channel_id), txid),
+            Some(0)
+        );
+        channel_id[30] ^= 0x01;
+        channel_id[31] ^= 0x02;
+        assert_eq!(
+            funding_output_index(&hex::encode(&channel_id), txid),
+            Some(0x0102)
+        );
+        channel_id[0] ^= 0x01;
+        assert_eq!(funding_output_index(&hex::encode(&channel_id), txid), None);
+    }
+
+    #[test]
+    fn test_fee_of_shared_sweep() {
+        // A sweep of an output of the closing transaction, one of an HTLC
+        // transaction and one of another channel.
+        let tx: EsploraTx = serde_json::from_value(serde_json::json!({
+            "vin": [
+                { "txid": "closing", "prevout": { "scriptpubkey_type": "v0_p2wsh", "value": 20_000 } },
+                { "txid": "htlc", "prevout": { "scriptpubkey_type": "v0_p2wsh", "value": 10_000 } },
+                { "txid": "other", "prevout": { "scriptpubkey_type": "v0_p2wsh", "value": 10_000 } },
+            ],
+            "vout": [{ "scriptpubkey_type": "v0_p2wpkh", "value": 39_000 }],
+            "fee": 1_000,
+            "status": { "confirmed": true, "block_height": 110, "block_time": 2_000 },
+        }))
+        .unwrap();
+
+        let parents = ["closing".to_string(), "htlc".to_string()];
+        let sweep = Sweep::new(&tx, &parents).unwrap();
+        assert_eq!(
+            sweep,
+            Sweep {
+                height: 110,
+                time: 2_000,
+                amount: 30_000,
+                fee: 750,
+            }
+        );
+        let other = Sweep::new(&tx, &["other".to_string()]).unwrap();
+        assert_eq!((other.amount, other.fee), (10_000, 250));
+    }
+
+    #[test]
+    fn test_unconfirmed_sweep() {
+        let tx: EsploraTx = serde_json::from_value(serde_json::json!({
+            "vin": [{ "txid": "closing", "prevout": { "scriptpubkey_type": "v0_p2wsh", "value": 20_000 } }],
+            "vout": [{ "scriptpubkey_type": "v0_p2wpkh", "value": 19_000 }],
+            "fee": 1_000,
+            "status": { "confirmed": false },
+        }))
+        .unwrap();
+        assert_eq!(Sweep::new(&tx, &["closing".to_string()]), None);
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::HashSet;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+#[cfg(feature = "node")]
+use std::str::FromStr;
 
 use aes_gcm::aead::Aead;
 use aes_gcm::{Aes256Gcm, Key, Nonce};
@@ -9,17 +11,38 @@ use anyhow::{anyhow, Context, Result};
 use bip39::Mnemonic;
 use bitcoin::bip32::{ChainCode, ChildNumber, Xpriv};
 use bitcoin::secp256k1::ffi::types::AlignedType;
-use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::secp256k1::{self, PublicKey, Secp256k1};
 use bitcoin::NetworkKind;
 use hmac::Hmac;
+#[cfg(feature = "node")]
+use ldk_node::bitcoin::BlockHash;
+#[cfg(feature = "node")]
+use ldk_node::lightning::chain::channelmonitor::ChannelMonitor;
+#[cfg(feature = "node")]
+use ldk_node::lightning::ln::msgs::SocketAddress;
+#[cfg(feature = "node")]
+use ldk_node::lightning::sign::{InMemorySigner, KeysManager};
+#[cfg(feature = "node")]
+use ldk_node::lightning::util::ser::ReadableArgs;
+#[cfg(feature = "node")]
 use ldk_node::KeyValue;
-use serde::Deserialize;
+use log::error;
+use serde::{Deserialize, Serialize};
 use sha2::Sha512;
+use zeroize::Zeroizing;
+
+use crate::failure::Failure;
 
 type HmacSha512 = Hmac<Sha512>;
 
+/// Lengths of the AES-GCM nonce and authentication tag in encrypted backups.
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
 #[derive(Deserialize, Debug)]
 pub struct StaticChannelBackup {
+    /// ID of the node the backup was created by.
+    pub node_id: Option<String>,
     pub channels: Vec<ChannelBackup>,
     pub monitors: Vec<EncodedChannelMonitorBackup>,
 }
@@ -28,6 +51,24 @@ impl StaticChannelBackup {
     pub fn channel_ids(&self) -> HashSet<String> {
         self.channels.iter().map(|c| c.channel_id.clone()).collect()
     }
+
+    pub fn summary(&self) -> BackupSummary {
+        BackupSummary {
+            node_id: self.node_id.clone(),
+            channels: self.channels.clone(),
+            monitors: self.monitors.iter().map(|m| m.summary()).collect(),
+        }
+    }
+
+    /// Channels monitored by the monitor with the given key, which is named
+    /// after the channel's funding outpoint.
+    pub fn channels_of_monitor(&self, key: &str) -> Vec<&ChannelBackup> {
+        let funding_tx_id = key.split('_').next().unwrap_or_default();
+        self.channels
+            .iter()
+            .filter(|c| c.funding_tx_id == funding_tx_id)
+            .collect()
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -38,6 +79,62 @@ pub struct EncodedChannelMonitorBackup {
     pub value: Vec<u8>,
 }
 
+impl EncodedChannelMonitorBackup {
+    pub fn update_id(&self) -> Option<u64> {
+        monitor_update_id(&self.value)
+    }
+
+    pub fn summary(&self) -> MonitorSummary {
+        MonitorSummary {
+            key: self.key.clone(),
+            size: self.value.len() as u64,
+            update_id: self.update_id(),
+        }
+    }
+}
+
+/// Contents of a backup, without the channel monitors themselves.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct BackupSummary {
+    pub node_id: Option<String>,
+    pub channels: Vec<ChannelBackup>,
+    pub monitors: Vec<MonitorSummary>,
+}
+
+/// A channel monitor from a backup, without its contents.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct MonitorSummary {
+    pub key: String,
+    /// Size of the encoded monitor in bytes.
+    pub size: u64,
+    pub update_id: Option<u64>,
+}
+
+/// Reads the ID of the latest update applied to a serialized channel monitor,
+/// which follows the two serialization version bytes. Update IDs grow with
+/// every change of the channel state, so of two copies of a monitor the one
+/// with the lower ID is outdated.
+pub fn monitor_update_id(encoded: &[u8]) -> Option<u64> {
+    let bytes = encoded.get(2..10)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Decodes a serialized channel monitor the way the node reads the monitors
+/// in its store when it starts, and returns its latest update ID. The keys
+/// only serve to derive the channel's signer, which reading does not check.
+#[cfg(feature = "node")]
+pub fn read_monitor(encoded: &[u8]) -> Result<u64> {
+    let keys = KeysManager::new(&[0; 32], 0, 0, true);
+    let mut reader = encoded;
+    let (_, monitor) =
+        <(BlockHash, ChannelMonitor<InMemorySigner>)>::read(&mut reader, (&keys, &keys))
+            .map_err(|e| anyhow!("invalid channel monitor: {}", e))?;
+    Ok(monitor.get_latest_update_id())
+}
+
+#[cfg(feature = "node")]
 impl From<EncodedChannelMonitorBackup> for KeyValue {
     fn from(backup: EncodedChannelMonitorBackup) -> Self {
         KeyValue {
@@ -47,7 +144,8 @@ impl From<EncodedChannelMonitorBackup> for KeyValue {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
 pub struct ChannelBackup {
     pub channel_id: String,
     pub peer_id: String,
@@ -55,6 +153,18 @@ pub struct ChannelBackup {
     pub funding_tx_id: String,
 }
 
+/// Parses the address of a peer as found in a backup.
+#[cfg(feature = "node")]
+pub fn parse_peer_address(s: &str) -> Result<SocketAddress> {
+    SocketAddress::from_str(s).map_err(|e| {
+        anyhow!(
+            "bad static channel backup: invalid peer address {}: {:?}",
+            s,
+            e
+        )
+    })
+}
+
 pub fn load_scb_guess_type<P>(path: P, mnemonic: &Mnemonic) -> Result<StaticChannelBackup>
 where
     P: AsRef<Path>,
@@ -64,33 +174,112 @@ where
         .context("failed to load SCB")
 }
 
+/// Parses the contents of a plain or encrypted backup file.
+pub fn parse_scb_guess_type(contents: &str, mnemonic: &Mnemonic) -> Result<StaticChannelBackup> {
+    parse_scb(contents)
+        .or_else(|_| parse_scb_encrypted(contents, mnemonic))
+        .context("failed to load SCB")
+}
+
+/// Parses the contents of a plain backup file.
+pub fn parse_scb(contents: &str) -> Result<StaticChannelBackup> {
+    serde_json::from_str(contents)
+        .context("failed to parse SCB file")
+        .context(Failure::BadBackup)
+}
+
+/// Parses a plain or encrypted backup and checks that it belongs to the seed
+/// phrase.
+pub fn inspect(contents: &str, mnemonic: &Mnemonic) -> Result<BackupSummary> {
+    let scb = parse_scb_guess_type(contents, mnemonic)?;
+    check_node_id(&scb, mnemonic)?;
+    Ok(BackupSummary {
+        node_id: Some(derive_node_id(mnemonic).to_string()),
+        ..scb.summary()
+    })
+}
+
 pub fn load_scb<P>(path: P) -> Result<StaticChannelBackup>
 where
     P: AsRef<Path>,
 {
     serde_json::from_reader(BufReader::new(
-        File::open(path).context("failed to open SCB file")?,
+        File::open(path)
+            .context("failed to open SCB file")
+            .context(Failure::BadBackup)?,
     ))
     .context("failed to parse SCB file")
+    .context(Failure::BadBackup)
 }
 
 pub fn load_scb_encrypted<P>(path: P, mnemonic: &Mnemonic) -> Result<StaticChannelBackup>
 where
     P: AsRef<Path>,
 {
-    let encrypted = std::fs::read_to_string(path).context("failed to read SCB file")?;
-    let plaintext = decrypt_scb_str(&encrypted, mnemonic)?;
-    serde_json::from_str(&plaintext).context("failed to parse SCB file")
+    let encrypted = std::fs::read_to_string(path)
+        .context("failed to read SCB file")
+        .context(Failure::BadBackup)?;
+    parse_scb_encrypted(&encrypted, mnemonic)
+}
+
+fn parse_scb_encrypted(encrypted: &str, mnemonic: &Mnemonic) -> Result<StaticChannelBackup> {
+    let plaintext = decrypt_scb_str(encrypted, mnemonic)?;
+    serde_json::from_str(&plaintext)
+        .context("failed to parse SCB file")
+        .context(Failure::BadBackup)
+}
+
+/// Checks that the backup was created by the node of the given seed phrase.
+/// Backups that do not name their node are accepted.
+pub fn check_node_id(scb: &StaticChannelBackup, mnemonic: &Mnemonic) -> Result<()> {
+    let Some(node_id) = &scb.node_id else {
+        return Ok(());
+    };
+
+    let derived_node_id = derive_node_id(mnemonic).to_string();
+    if *node_id != derived_node_id {
+        error!(
+            "backup node ID {} does not match node ID {} derived from the seed phrase",
+            node_id, derived_node_id
+        );
+        return Err(anyhow!("seed phrase does not match the backup")).context(Failure::WrongSeed);
+    }
+    Ok(())
+}
+
+/// Derives the ID of the LDK node that uses the given seed phrase.
+pub fn derive_node_id(mnemonic: &Mnemonic) -> PublicKey {
+    let secp = Secp256k1::new();
+
+    // LDK node seeds its keys manager with the master key derived from the
+    // BIP39 seed; the node secret is the first hardened child of the master
+    // key derived from that.
+    let seed = Zeroizing::new(mnemonic.to_seed(""));
+    let mut master = Xpriv::new_master(NetworkKind::Main, &*seed).unwrap();
+    let ldk_seed = Zeroizing::new(master.private_key.secret_bytes());
+    master.private_key.non_secure_erase();
+
+    let mut ldk_master = Xpriv::new_master(NetworkKind::Main, &*ldk_seed).unwrap();
+    let mut node_secret = ldk_master
+        .derive_priv(&secp, &[ChildNumber::from_hardened_idx(0).unwrap()])
+        .unwrap()
+        .private_key;
+    ldk_master.private_key.non_secure_erase();
+
+    let node_id = PublicKey::from_secret_key(&secp, &node_secret);
+    node_secret.non_secure_erase();
+    node_id
 }
 
 fn master_key(mnemonic: &Mnemonic) -> Xpriv {
     use hmac::Mac;
 
-    let seed = mnemonic.to_seed("");
+    let seed = Zeroizing::new(mnemonic.to_seed(""));
 
     let mut mac = HmacSha512::new_from_slice(b"Bitcoin seed").unwrap();
-    mac.update(&seed);
-    let hmac_result = mac.finalize().into_bytes();
+    mac.update(&*seed);
+    let mut hmac_result = Zeroizing::new([0u8; 64]);
+    hmac_result.copy_from_slice(&mac.finalize().into_bytes());
 
     let chain_code: [u8; 32] = hmac_result[32..64].try_into().unwrap();
 
@@ -104,24 +293,31 @@ fn master_key(mnemonic: &Mnemonic) -> Xpriv {
     }
 }
 
-fn derive_scb_key(mnemonic: &Mnemonic) -> Key<Aes256Gcm> {
+/// Derives the key the backup is encrypted with. The key is wiped when the
+/// returned value is dropped.
+fn derive_scb_key(mnemonic: &Mnemonic) -> Zeroizing<[u8; 32]> {
     let mut buf: Vec<AlignedType> = Vec::with_capacity(Secp256k1::preallocate_size());
     buf.resize(Secp256k1::preallocate_size(), AlignedType::zeroed());
     let secp = Secp256k1::preallocated_new(buf.as_mut_slice()).unwrap();
 
-    let root = master_key(mnemonic);
+    let mut root = master_key(mnemonic);
 
-    let app_key = root
+    let mut app_key = root
         .derive_priv(
             &secp,
             &vec![ChildNumber::from_hardened_idx(128029).unwrap()],
         )
         .unwrap();
-    let ret = app_key
+    let mut ret = app_key
         .derive_priv(&secp, &vec![ChildNumber::from_hardened_idx(0).unwrap()])
         .unwrap();
+    let key = Zeroizing::new(ret.private_key.secret_bytes());
+
+    root.private_key.non_secure_erase();
+    app_key.private_key.non_secure_erase();
+    ret.private_key.non_secure_erase();
 
-    Key::<Aes256Gcm>::from(ret.private_key.secret_bytes())
+    key
 }
 
 fn decrypt(nonce: &[u8], ciphertext: &[u8], key: &Key<Aes256Gcm>) -> Result<Vec<u8>> {
@@ -129,34 +325,72 @@ fn decrypt(nonce: &[u8], ciphertext: &[u8], key: &Key<Aes256Gcm>) -> Result<Vec<
 
     let cipher = Aes256Gcm::new(key);
     cipher
-        .decrypt(&Nonce::from_slice(nonce), ciphertext)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
         .map_err(|e| anyhow!("{}", e))
         .context("failed to decrypt ciphertext")
 }
 
 fn decrypt_scb(nonce: &[u8], ciphertext: &[u8], mnemonic: &Mnemonic) -> Result<Vec<u8>> {
     let key = derive_scb_key(mnemonic);
-    decrypt(nonce, ciphertext, &key)
+    decrypt(nonce, ciphertext, Key::<Aes256Gcm>::from_slice(&*key))
 }
 
-fn decrypt_scb_str(xs: &str, mnemonic: &Mnemonic) -> Result<String> {
+/// Splits an encrypted backup, `<nonce>-<ciphertext>` in hex, into the nonce
+/// and the ciphertext. Whitespace is ignored, as email clients and editors
+/// tend to wrap long lines.
+pub fn parse_envelope(xs: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+    let xs: String = xs.chars().filter(|c| !c.is_whitespace()).collect();
     let parts = xs.split('-').collect::<Vec<_>>();
     if parts.len() != 2 {
-        return Err(anyhow!("invalid SCB format"));
+        return Err(anyhow!("invalid SCB format")).context(Failure::BadBackup);
     }
 
-    let nonce = hex::decode(parts[0]).context("failed to decode nonce")?;
-    let ciphertext = hex::decode(parts[1]).context("failed to decode encrypted data")?;
+    let nonce = hex::decode(parts[0])
+        .context("failed to decode nonce")
+        .context(Failure::BadBackup)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(anyhow!("invalid nonce length: {}", nonce.len())).context(Failure::BadBackup);
+    }
+    let ciphertext = hex::decode(parts[1])
+        .context("failed to decode encrypted data")
+        .context(Failure::BadBackup)?;
+    if ciphertext.len() < TAG_LEN {
+        return Err(anyhow!("encrypted data is truncated")).context(Failure::BadBackup);
+    }
 
-    let plaintext = decrypt_scb(&nonce, &ciphertext, mnemonic)?;
+    Ok((nonce, ciphertext))
+}
+
+fn decrypt_scb_str(xs: &str, mnemonic: &Mnemonic) -> Result<String> {
+    let (nonce, ciphertext) = parse_envelope(xs)?;
 
-    Ok(String::from_utf8(plaintext)?)
+    // Authenticated decryption fails if the key, and thus the seed phrase, is
+    // wrong, but also if the encrypted data has been cut short.
+    let plaintext = decrypt_scb(&nonce, &ciphertext, mnemonic).context(Failure::WrongSeed)?;
+
+    String::from_utf8(plaintext)
+        .context("decrypted SCB is not valid UTF-8")
+        .context(Failure::BadBackup)
 }
 
 #[cfg(test)]
 mod tests {
+    use aes_gcm::KeyInit;
+    use proptest::prelude::*;
+
     use super::*;
 
+    const MNEMONIC: &str = "limit reward expect search tissue call visa fit thank cream brave jump";
+
+    fn encrypt(plaintext: &str, nonce: &[u8; NONCE_LEN], mnemonic: &Mnemonic) -> String {
+        let key = derive_scb_key(mnemonic);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&*key));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(nonce), plaintext.as_bytes())
+            .unwrap();
+        format!("{}-{}", hex::encode(nonce), hex::encode(ciphertext))
+    }
+
     #[test]
     fn test_decrypt() {
         let xs = "3fd21f9a393d8345ddbdd449-ba05c3dbafdfb7eea574373b7763d0c81c599b2cd1735e59a1c5571379498f4da8fe834c3403824ab02b61005abc1f563c638f425c65420e82941efe94794555c8b145a0603733ee115277f860011e6a17fd8c22f1d73a096ff7275582aac19b430940b40a2559c7ff59a063305290ef7c9ba46f9de17b0ddbac9030b0";
@@ -167,4 +401,61 @@ mod tests {
 
         assert_eq!(plaintext, "{\"node_id\":\"037e702144c4fa485d42f0f69864e943605823763866cf4bf619d2d2cf2eda420b\",\"channels\":[],\"monitors\":[]}\n");
     }
+
+    #[test]
+    fn test_monitor_update_id() {
+        assert_eq!(
+            monitor_update_id(&[1, 1, 0, 0, 0, 0, 0, 0, 1, 2, 0xff]),
+            Some(258)
+        );
+        assert_eq!(monitor_update_id(&[1, 1, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_derive_node_id() {
+        let mnemonic = "limit reward expect search tissue call visa fit thank cream brave jump";
+        let mnemonic = Mnemonic::parse(mnemonic).unwrap();
+
+        assert_eq!(
+            derive_node_id(&mnemonic).to_string(),
+            "037e702144c4fa485d42f0f69864e943605823763866cf4bf619d2d2cf2eda420b"
+        );
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_encrypt_decrypt(plaintext in "\\PC*", nonce in any::<[u8; NONCE_LEN]>()) {
+            let mnemonic = Mnemonic::parse(MNEMONIC).unwrap();
+            let encrypted = encrypt(&plaintext, &nonce, &mnemonic);
+            prop_assert_eq!(decrypt_scb_str(&encrypted, &mnemonic).unwrap(), plaintext);
+        }
+
+        #[test]
+        fn test_decrypt_wrapped(
+            nonce in any::<[u8; NONCE_LEN]>(),
+            breaks in prop::collection::vec((any::<prop::sample::Index>(), "[ \t\r\n]+"), 0..8),
+        ) {
+            let mnemonic = Mnemonic::parse(MNEMONIC).unwrap();
+            let mut encrypted = encrypt("{}", &nonce, &mnemonic);
+            for (index, whitespace) in breaks {
+                encrypted.insert_str(index.index(encrypted.len() + 1), &whitespace);
+            }
+            prop_assert_eq!(decrypt_scb_str(&encrypted, &mnemonic).unwrap(), "{}");
+        }
+
+        #[test]
+        fn test_decrypt_truncated(nonce in any::<[u8; NONCE_LEN]>(), cut in any::<prop::sample::Index>()) {
+            let mnemonic = Mnemonic::parse(MNEMONIC).unwrap();
+            let encrypted = encrypt("{\"channels\":[],\"monitors\":[]}", &nonce, &mnemonic);
+            let truncated = &encrypted[..cut.index(encrypted.len())];
+            prop_assert!(parse_scb_guess_type(truncated, &mnemonic).is_err());
+        }
+
+        #[test]
+        fn test_parse_envelope_does_not_panic(xs in "[0-9a-f\\- \n]*") {
+            let _ = parse_envelope(&xs);
+        }
+    }
 }
@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+/// What happened since the previous wallet sync.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activity {
+    /// Nothing changed.
+    Idle,
+    /// A new block arrived, balances changed or the sync failed.
+    Changed,
+    /// Some funds are about to confirm or unlock.
+    ExpectingConfirmation,
+}
+
+/// Adaptive wallet sync schedule.
+///
+/// The interval doubles up to `max` for every sync that brings nothing new
+/// and drops back to `min` as soon as something changes or confirmations are
+/// expected.
+pub struct SyncSchedule {
+    min: Duration,
+    max: Duration,
+    interval: Duration,
+    next: Instant,
+}
+
+impl SyncSchedule {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            interval: min,
+            next: Instant::now() + min,
+        }
+    }
+
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.next
+    }
+
+    pub fn time_until_next(&self) -> Duration {
+        self.next.saturating_duration_since(Instant::now())
+    }
+
+    pub fn schedule_next(&mut self, activity: Activity) {
+        self.interval = match activity {
+            Activity::Idle => (self.interval * 2).min(self.max),
+            Activity::Changed | Activity::ExpectingConfirmation => self.min,
+        };
+        self.next = Instant::now() + self.interval;
+    }
+
+    /// Makes the next sync happen no later than `min` from now.
+    pub fn speed_up(&mut self) {
+        self.interval = self.min;
+        self.next = self.next.min(Instant::now() + self.min);
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: Duration = Duration::from_secs(10);
+    const MAX: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_backoff() {
+        let mut schedule = SyncSchedule::new(MIN, MAX);
+        assert_eq!(schedule.interval(), MIN);
+        assert!(!schedule.is_due());
+
+        let mut intervals = Vec::new();
+        for _ in 0..4 {
+            schedule.schedule_next(Activity::Idle);
+            intervals.push(schedule.interval().as_secs());
+        }
+        assert_eq!(intervals, vec![20, 40, 60, 60]);
+        assert!(schedule.time_until_next() > Duration::from_secs(40));
+
+        schedule.schedule_next(Activity::ExpectingConfirmation);
+        assert_eq!(schedule.interval(), MIN);
+        schedule.schedule_next(Activity::Idle);
+        schedule.schedule_next(Activity::Changed);
+        assert_eq!(schedule.interval(), MIN);
+        assert!(schedule.time_until_next() <= MIN);
+    }
+
+    #[test]
+    fn test_speed_up() {
+        let mut schedule = SyncSchedule::new(MIN, MAX);
+        schedule.schedule_next(Activity::Idle);
+        schedule.schedule_next(Activity::Idle);
+        assert!(schedule.time_until_next() > MIN);
+
+        schedule.speed_up();
+        assert_eq!(schedule.interval(), MIN);
+        assert!(schedule.time_until_next() <= MIN);
+
+        // A sync already due sooner is not postponed.
+        schedule.next = Instant::now();
+        schedule.speed_up();
+        assert!(schedule.is_due());
+    }
+
+    #[test]
+    fn test_max_below_min() {
+        let mut schedule = SyncSchedule::new(MAX, MIN);
+        schedule.schedule_next(Activity::Idle);
+        assert_eq!(schedule.interval(), MAX);
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+
+use bip39::{Language, Mnemonic};
+use zeroize::Zeroizing;
+
+/// Word counts allowed by BIP39.
+const WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
+
+/// Maximum number of suggestions shown for a misspelled word.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Reasons a seed phrase was rejected, detailed enough to tell the user what
+/// to fix.
+#[derive(Debug, PartialEq)]
+pub enum SeedError {
+    WordCount(usize),
+    /// Words not in the BIP39 wordlist: position (1-based), word and
+    /// suggestions.
+    UnknownWords(Vec<(usize, String, Vec<&'static str>)>),
+    Checksum,
+}
+
+impl fmt::Display for SeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeedError::WordCount(n) => write!(
+                f,
+                "the recovery phrase has {} words, expected 12, 15, 18, 21 or 24",
+                n
+            ),
+            SeedError::UnknownWords(words) => {
+                write!(f, "the recovery phrase contains unknown words:")?;
+                for (pos, word, suggestions) in words {
+                    match word.is_empty() {
+                        true => write!(f, "\n  word {}", pos)?,
+                        false => write!(f, "\n  word {} \"{}\"", pos, word)?,
+                    }
+                    if !suggestions.is_empty() {
+                        write!(f, ", did you mean: {}?", suggestions.join(", "))?;
+                    }
+                }
+                Ok(())
+            }
+            SeedError::Checksum => write!(
+                f,
+                "all words are valid, but the checksum does not match; check that no word is mistyped or swapped and that the order is correct"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SeedError {}
+
+impl SeedError {
+    /// Drops the words from the error, leaving only their positions, for
+    /// errors that may end up in logs.
+    pub fn redacted(self) -> Self {
+        match self {
+            SeedError::UnknownWords(words) => SeedError::UnknownWords(
+                words
+                    .into_iter()
+                    .map(|(pos, _, _)| (pos, String::new(), Vec::new()))
+                    .collect(),
+            ),
+            e => e,
+        }
+    }
+}
+
+/// Parses an English BIP39 seed phrase, checking each word against the
+/// wordlist before checking the checksum.
+pub fn parse_mnemonic(s: &str) -> Result<Mnemonic, SeedError> {
+    let words: Zeroizing<Vec<String>> =
+        Zeroizing::new(s.split_whitespace().map(|w| w.to_lowercase()).collect());
+
+    if !WORD_COUNTS.contains(&words.len()) {
+        return Err(SeedError::WordCount(words.len()));
+    }
+
+    let unknown: Vec<_> = words
+        .iter()
+        .enumerate()
+        .filter(|(_, w)| Language::English.find_word(w).is_none())
+        .map(|(i, w)| (i + 1, w.clone(), suggest(w)))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(SeedError::UnknownWords(unknown));
+    }
+
+    Mnemonic::parse_in_normalized(Language::English, &Zeroizing::new(words.join(" ")))
+        .map_err(|_| SeedError::Checksum)
+}
+
+/// Finds the wordlist entries closest to a misspelled word. BIP39 words are
+/// unique by their first four letters, so a matching prefix is a strong hint.
+fn suggest(word: &str) -> Vec<&'static str> {
+    // The word may contain any characters, so the prefix is taken by
+    // characters rather than bytes.
+    let prefix: String = word.chars().take(4).collect();
+    if prefix.chars().count() == 4 {
+        let by_prefix = Language::English.words_by_prefix(&prefix);
+        if !by_prefix.is_empty() {
+            return by_prefix.iter().take(MAX_SUGGESTIONS).copied().collect();
+        }
+    }
+
+    let mut candidates: Vec<(usize, &'static str)> = Language::English
+        .word_list()
+        .iter()
+        .map(|w| (edit_distance(word, w), *w))
+        .filter(|(d, _)| *d <= 2)
+        .collect();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, w)| w)
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(cur).min(row[j])
+            };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mnemonic() {
+        let abandon = |n| vec!["abandon"; n].join(" ");
+
+        assert!(parse_mnemonic(&format!("{} about", abandon(11))).is_ok());
+        assert!(parse_mnemonic(&format!("{} ART", abandon(23))).is_ok());
+        assert_eq!(
+            parse_mnemonic(&abandon(12)).unwrap_err(),
+            SeedError::Checksum
+        );
+        assert_eq!(
+            parse_mnemonic(&abandon(13)).unwrap_err(),
+            SeedError::WordCount(13)
+        );
+
+        match parse_mnemonic(&format!("{} abuot", abandon(11))).unwrap_err() {
+            SeedError::UnknownWords(words) => {
+                assert_eq!(words.len(), 1);
+                assert_eq!(words[0].0, 12);
+                assert!(words[0].2.contains(&"about"));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        match parse_mnemonic(&format!("{} aaaé", abandon(11))).unwrap_err() {
+            SeedError::UnknownWords(words) => assert_eq!(words[0].1, "aaaé"),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+}
@@ -9,18 +9,25 @@ use serde::{Deserialize, Serialize};
 pub enum ChannelState {
     Pending,
     ForceCloseInitiated,
+    /// The channel monitor in the backup is outdated, so it was not restored
+    /// and the channel is left to the peer to close.
+    StaleBackup,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct State {
     /// Map of channel states by peer ID.
     by_peer: HashMap<String, HashMap<String, ChannelState>>,
+    /// Highest update ID seen for each channel monitor, by monitor key.
+    #[serde(default)]
+    monitor_update_ids: HashMap<String, u64>,
 }
 
 impl State {
     pub fn new() -> Self {
         Self {
             by_peer: HashMap::new(),
+            monitor_update_ids: HashMap::new(),
         }
     }
 
@@ -53,8 +60,8 @@ impl State {
 
     pub fn get_all_channel_ids(&self) -> HashSet<String> {
         self.by_peer
-            .iter()
-            .flat_map(|(_, v)| v.keys().cloned())
+            .values()
+            .flat_map(|v| v.keys().cloned())
             .collect()
     }
 
@@ -65,10 +72,24 @@ impl State {
             .cloned()
     }
 
+    pub fn has_stale_channels(&self) -> bool {
+        self.by_peer
+            .values()
+            .any(|v| v.values().any(|&s| s == ChannelState::StaleBackup))
+    }
+
+    pub fn monitor_update_id(&self, key: &str) -> Option<u64> {
+        self.monitor_update_ids.get(key).copied()
+    }
+
+    pub fn set_monitor_update_id(&mut self, key: &str, update_id: u64) {
+        self.monitor_update_ids.insert(key.to_string(), update_id);
+    }
+
     pub fn set_channel_state(&mut self, peer: &str, channel_id: &str, state: ChannelState) {
         self.by_peer
             .entry(peer.to_string())
-            .or_insert_with(HashMap::new)
+            .or_default()
             .insert(channel_id.to_string(), state);
     }
 }
@@ -78,3 +99,40 @@ impl Default for State {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn channel_state() -> impl Strategy<Value = ChannelState> {
+        prop_oneof![
+            Just(ChannelState::Pending),
+            Just(ChannelState::ForceCloseInitiated),
+            Just(ChannelState::StaleBackup),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_save_load(
+            channels in prop::collection::vec(("\\PC*", "\\PC*", channel_state()), 0..10),
+            monitor_update_ids in prop::collection::hash_map("\\PC*", any::<u64>(), 0..5),
+        ) {
+            let mut state = State::new();
+            for (peer, channel_id, channel_state) in &channels {
+                state.set_channel_state(peer, channel_id, *channel_state);
+            }
+            for (key, update_id) in &monitor_update_ids {
+                state.set_monitor_update_id(key, *update_id);
+            }
+
+            let path = std::env::temp_dir().join(format!("hub-recovery-state-{}", std::process::id()));
+            state.save(&path).unwrap();
+            let loaded = State::try_load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            prop_assert_eq!(loaded, Some(state));
+        }
+    }
+}
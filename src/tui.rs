@@ -0,0 +1,275 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ratatui::crossterm::event::{self, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::balance::BalanceReport;
+use crate::progress::{self, Snapshot};
+
+/// Number of lines kept in the event log.
+const MAX_EVENTS: usize = 200;
+
+/// Average time between blocks, used for unlock time estimates.
+const BLOCK_INTERVAL_SECS: u64 = 600;
+
+/// Keyboard and terminal input relevant to the main loop.
+pub enum Input {
+    Quit,
+    Resize,
+}
+
+/// Blocks until the next relevant input. Returns `None` if input can no
+/// longer be read.
+pub fn read_input() -> Option<Input> {
+    loop {
+        match event::read().ok()? {
+            event::Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Some(Input::Quit),
+                // Raw mode turns Ctrl-C into a key press instead of a signal.
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Some(Input::Quit)
+                }
+                _ => {}
+            },
+            event::Event::Resize(_, _) => return Some(Input::Resize),
+            _ => {}
+        }
+    }
+}
+
+/// Full-screen view of the recovery progress. The terminal is restored when
+/// the dashboard is dropped.
+pub struct Dashboard {
+    terminal: DefaultTerminal,
+    snapshot: Option<Snapshot>,
+    events: VecDeque<String>,
+    dirty: bool,
+}
+
+impl Dashboard {
+    pub fn enter() -> Result<Self> {
+        let terminal = ratatui::try_init().context("failed to initialize terminal")?;
+        Ok(Self {
+            terminal,
+            snapshot: None,
+            events: VecDeque::new(),
+            dirty: true,
+        })
+    }
+
+    /// Shows the given snapshot, redrawing only if it differs from the
+    /// current one.
+    pub fn update(&mut self, snapshot: Snapshot) -> Result<()> {
+        if self.snapshot.as_ref() != Some(&snapshot) {
+            self.snapshot = Some(snapshot);
+            self.dirty = true;
+        }
+        self.draw_if_dirty()
+    }
+
+    /// Appends a line to the event log.
+    pub fn log(&mut self, msg: &str) -> Result<()> {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events
+            .push_back(format!("{} {}", format_time(progress::now()), msg));
+        self.dirty = true;
+        self.draw_if_dirty()
+    }
+
+    /// Redraws everything, e.g. after the terminal has been resized.
+    pub fn redraw(&mut self) -> Result<()> {
+        self.terminal.clear().context("failed to clear terminal")?;
+        self.dirty = true;
+        self.draw_if_dirty()
+    }
+
+    fn draw_if_dirty(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let Self {
+            terminal,
+            snapshot,
+            events,
+            ..
+        } = self;
+        terminal
+            .draw(|frame| render(frame, snapshot.as_ref(), events))
+            .context("failed to draw dashboard")?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let _ = ratatui::try_restore();
+    }
+}
+
+fn render(frame: &mut Frame, snapshot: Option<&Snapshot>, events: &VecDeque<String>) {
+    let [top, channels, log, footer] = Layout::vertical([
+        Constraint::Length(7),
+        Constraint::Min(5),
+        Constraint::Length(10),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [wallet, sync] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+
+    match snapshot {
+        Some(s) => {
+            render_wallet(frame, wallet, &s.report);
+            render_sync(frame, sync, s);
+            render_channels(frame, channels, s);
+        }
+        None => frame.render_widget(
+            Paragraph::new("Loading...").block(Block::bordered().title(" Channels ")),
+            channels,
+        ),
+    }
+
+    let items: Vec<ListItem> = events
+        .iter()
+        .rev()
+        .take(log.height.saturating_sub(2) as usize)
+        .map(|e| ListItem::new(e.as_str()))
+        .collect();
+    frame.render_widget(
+        List::new(items).block(Block::bordered().title(" Events (newest first) ")),
+        log,
+    );
+
+    frame.render_widget(
+        Line::from("Press q to stop. The recovery can be resumed later.").dim(),
+        footer,
+    );
+}
+
+fn render_wallet(frame: &mut Frame, area: Rect, report: &BalanceReport) {
+    let lines = vec![
+        Line::from(format!("Spendable:     {} sats", report.spendable)),
+        Line::from(format!("Total:         {} sats", report.total)),
+        Line::from(format!("Reserved:      {} sats", report.reserved)),
+        Line::from(format!("Claimable:     {} sats", report.claimable_total())),
+        Line::from(format!(
+            "Pending sweep: {} sats",
+            report.pending_sweep_total()
+        )),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" On-chain wallet ")),
+        area,
+    );
+}
+
+fn render_sync(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let last_sync = snapshot
+        .last_sync
+        .map(format_time)
+        .unwrap_or_else(|| "never".to_string());
+    let lines = vec![
+        Line::from(format!("Tip height: {}", snapshot.tip_height)),
+        Line::from(format!("Last sync:  {}", last_sync)),
+        Line::from(format!("Source:     {}", snapshot.chain_source)),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(ratatui::widgets::Wrap { trim: true })
+            .block(Block::bordered().title(" Chain sync ")),
+        area,
+    );
+}
+
+fn render_channels(frame: &mut Frame, area: Rect, snapshot: &Snapshot) {
+    let rows = snapshot.channels.iter().map(|c| {
+        Row::new(vec![
+            shorten(&c.peer_id),
+            shorten(&c.channel_id),
+            c.status.to_string(),
+            c.amount.to_string(),
+            describe_unlock(c.unlock_height, snapshot.tip_height),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(15),
+            Constraint::Length(15),
+            Constraint::Length(18),
+            Constraint::Length(12),
+            Constraint::Min(20),
+        ],
+    )
+    .header(
+        Row::new(vec!["Peer", "Channel", "State", "Sats", "Unlocks"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::bordered().title(" Channels "));
+    frame.render_widget(table, area);
+}
+
+fn describe_unlock(unlock_height: Option<u32>, tip_height: u32) -> String {
+    match unlock_height {
+        None => String::new(),
+        Some(h) if h <= tip_height => format!("{} (unlocked)", h),
+        Some(h) => {
+            let eta = Duration::from_secs(u64::from(h - tip_height) * BLOCK_INTERVAL_SECS);
+            format!("{} (~{})", h, format_duration(eta))
+        }
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let mins = d.as_secs() / 60;
+    match mins {
+        m if m < 120 => format!("{} min", m),
+        m if m < 48 * 60 => format!("{} h", m / 60),
+        m => format!("{} days", m / (24 * 60)),
+    }
+}
+
+fn shorten(id: &str) -> String {
+    // Ids from a backup are not necessarily ASCII.
+    let chars: Vec<char> = id.chars().collect();
+    match chars.len() {
+        n if n > 13 => {
+            let start: String = chars[..6].iter().collect();
+            let end: String = chars[n - 6..].iter().collect();
+            format!("{}…{}", start, end)
+        }
+        _ => id.to_string(),
+    }
+}
+
+/// Formats a UNIX timestamp as the UTC time of day.
+fn format_time(timestamp: u64) -> String {
+    let secs = timestamp % 86400;
+    format!(
+        "{:02}:{:02}:{:02} UTC",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("abcdef"), "abcdef");
+        assert_eq!(shorten("0123456789abcdef"), "012345…abcdef");
+        assert_eq!(shorten("ééééééééééééééé"), "éééééé…éééééé");
+    }
+}
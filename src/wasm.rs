@@ -0,0 +1,43 @@
+//! WebAssembly exports of the backup functions, for inspecting backups in a
+//! browser.
+
+use bip39::Mnemonic;
+use wasm_bindgen::prelude::*;
+
+use crate::scb::{self, BackupSummary};
+use crate::seed;
+
+fn js_error(e: anyhow::Error) -> JsError {
+    JsError::new(&format!("{:#}", e))
+}
+
+fn parse_mnemonic(seed_phrase: &str) -> Result<Mnemonic, JsError> {
+    // The message may end up in the browser console.
+    seed::parse_mnemonic(seed_phrase)
+        .map_err(|e| JsError::new(&format!("invalid seed phrase: {}", e.redacted())))
+}
+
+fn to_js(summary: &BackupSummary) -> Result<JsValue, JsError> {
+    serde_wasm_bindgen::to_value(summary).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Derives the ID of the node that uses the given seed phrase.
+#[wasm_bindgen(js_name = deriveNodeId)]
+pub fn derive_node_id(seed_phrase: &str) -> Result<String, JsError> {
+    Ok(scb::derive_node_id(&parse_mnemonic(seed_phrase)?).to_string())
+}
+
+/// Decrypts and parses a plain or encrypted backup, checks that it belongs to
+/// the seed phrase and returns its node ID, channels and channel monitors.
+#[wasm_bindgen(js_name = inspectBackup)]
+pub fn inspect_backup(backup: &str, seed_phrase: &str) -> Result<JsValue, JsError> {
+    let mnemonic = parse_mnemonic(seed_phrase)?;
+    to_js(&scb::inspect(backup, &mnemonic).map_err(js_error)?)
+}
+
+/// Parses a plain backup, which needs no seed phrase, and returns its node ID,
+/// channels and channel monitors.
+#[wasm_bindgen(js_name = parseBackup)]
+pub fn parse_backup(backup: &str) -> Result<JsValue, JsError> {
+    to_js(&scb::parse_scb(backup).map_err(js_error)?.summary())
+}
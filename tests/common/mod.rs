@@ -0,0 +1,401 @@
+//! Regtest environment for the end-to-end tests: a local bitcoind and electrs,
+//! Lightning nodes playing the hub and its peer, and helpers to run the
+//! recovery tool against them.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use electrsd::corepc_node::{self, Node as BitcoinD};
+use electrsd::ElectrsD;
+use ldk_node::bip39::Mnemonic;
+use ldk_node::bitcoin::{Address, Amount, Network};
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use ldk_node::{Builder, Event, Node};
+use serde_json::{json, Value};
+
+pub const HUB_SEED: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+const PEER_SEED: &str =
+    "legal winner thank year wave sausage worth useful legal winner thank yellow";
+
+const TIMEOUT: Duration = Duration::from_secs(600);
+
+pub struct TestEnv {
+    pub bitcoind: BitcoinD,
+    pub electrsd: ElectrsD,
+    pub dir: PathBuf,
+}
+
+impl TestEnv {
+    /// Starts bitcoind and electrs with binaries downloaded at build time, or
+    /// the ones given by BITCOIND_EXE and ELECTRS_EXE.
+    pub fn new(name: &str) -> Self {
+        let bitcoind_exe = env::var("BITCOIND_EXE")
+            .ok()
+            .or_else(|| corepc_node::downloaded_exe_path().ok())
+            .expect("set BITCOIND_EXE or enable a bitcoind version feature of electrsd");
+        let mut bitcoind_conf = corepc_node::Conf::default();
+        bitcoind_conf.network = "regtest";
+        bitcoind_conf.args.push("-rest");
+        let bitcoind = BitcoinD::with_conf(bitcoind_exe, &bitcoind_conf).unwrap();
+
+        let electrs_exe = env::var("ELECTRS_EXE")
+            .ok()
+            .or_else(electrsd::downloaded_exe_path)
+            .expect("set ELECTRS_EXE or enable an electrs version feature of electrsd");
+        let mut electrsd_conf = electrsd::Conf::default();
+        electrsd_conf.http_enabled = true;
+        electrsd_conf.network = "regtest";
+        let electrsd = ElectrsD::with_conf(electrs_exe, &bitcoind, &electrsd_conf).unwrap();
+
+        let dir = env::temp_dir().join(format!("hub-recovery-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let _ = bitcoind.client.create_wallet("test");
+        let _ = bitcoind.client.load_wallet("test");
+
+        Self {
+            bitcoind,
+            electrsd,
+            dir,
+        }
+    }
+
+    pub fn esplora_url(&self) -> String {
+        format!("http://{}", self.electrsd.esplora_url.as_ref().unwrap())
+    }
+
+    fn esplora_get(&self, path: &str) -> reqwest::blocking::Response {
+        reqwest::blocking::get(format!("{}{}", self.esplora_url(), path))
+            .and_then(|r| r.error_for_status())
+            .unwrap()
+    }
+
+    pub fn tip_height(&self) -> u64 {
+        self.esplora_get("/blocks/tip/height")
+            .text()
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap()
+    }
+
+    /// Mines blocks and waits until electrs has indexed them.
+    pub fn mine(&self, blocks: usize) {
+        let height = self.tip_height();
+        let address = self.bitcoind.client.new_address().unwrap();
+        let _ = self.bitcoind.client.generate_to_address(blocks, &address);
+        wait_until("electrs to index new blocks", || {
+            self.tip_height() >= height + blocks as u64
+        });
+    }
+
+    pub fn new_address(&self) -> Address {
+        self.bitcoind.client.new_address().unwrap()
+    }
+
+    /// Sats received by the address, including unconfirmed transactions.
+    pub fn received(&self, address: &Address) -> u64 {
+        let info: Value = serde_json::from_str(
+            &self
+                .esplora_get(&format!("/address/{}", address))
+                .text()
+                .unwrap(),
+        )
+        .unwrap();
+        ["chain_stats", "mempool_stats"]
+            .iter()
+            .map(|s| info[s]["funded_txo_sum"].as_u64().unwrap_or(0))
+            .sum()
+    }
+
+    /// Starts a Lightning node, listening on a free local port.
+    pub fn start_node(&self, name: &str, seed: &str) -> (Node, SocketAddress) {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = SocketAddress::from_str(&format!("127.0.0.1:{}", port)).unwrap();
+
+        let mut builder = Builder::new();
+        builder
+            .set_entropy_bip39_mnemonic(Mnemonic::parse(seed).unwrap(), None)
+            .set_network(Network::Regtest)
+            .set_storage_dir_path(self.dir.join(name).to_str().unwrap().to_string())
+            .set_chain_source_esplora(self.esplora_url(), None);
+        builder
+            .set_listening_addresses(vec![address.clone()])
+            .unwrap();
+
+        let node = builder.build().unwrap();
+        node.start().unwrap();
+        (node, address)
+    }
+
+    pub fn fund(&self, node: &Node, amount: Amount) {
+        let address = node.onchain_payment().new_address().unwrap();
+        self.bitcoind
+            .client
+            .send_to_address(&address, amount)
+            .unwrap();
+        self.mine(1);
+        node.sync_wallets().unwrap();
+    }
+
+    /// Starts the peer node and a hub node with a channel to it, and writes
+    /// the hub's static channel backup to `backup`. The hub is stopped, as the
+    /// recovery takes its place. Returns the peer, an address to sweep to and
+    /// the hub's on-chain balance besides the channel, which a sweep includes.
+    pub fn setup_hub_with_channel(&self, backup: &Path) -> (Node, Address, u64) {
+        self.mine(101);
+
+        let (peer, peer_address) = self.start_node("peer", PEER_SEED);
+        let (hub, _) = self.start_node("hub", HUB_SEED);
+        self.fund(&hub, Amount::from_sat(1_000_000));
+
+        hub.open_channel(
+            peer.node_id(),
+            peer_address.clone(),
+            500_000,
+            Some(100_000_000),
+            None,
+        )
+        .unwrap();
+        wait_for_event(&hub, |e| matches!(e, Event::ChannelPending { .. }));
+        wait_for_event(&peer, |e| matches!(e, Event::ChannelPending { .. }));
+
+        self.mine(6);
+        hub.sync_wallets().unwrap();
+        peer.sync_wallets().unwrap();
+        wait_for_event(&hub, |e| matches!(e, Event::ChannelReady { .. }));
+        wait_for_event(&peer, |e| matches!(e, Event::ChannelReady { .. }));
+
+        write_backup(&hub, &peer_address, backup);
+        let onchain_balance = hub.list_balances().total_onchain_balance_sats;
+        hub.stop().unwrap();
+
+        (peer, self.new_address(), onchain_balance)
+    }
+
+    /// Starts the recovery tool on this environment's chain.
+    pub fn spawn_recovery(&self, backup: &Path, sweep_address: Option<&Address>) -> Recovery {
+        self.spawn_recovery_with_args(backup, sweep_address, &[])
+    }
+
+    /// Like [`Self::spawn_recovery`], with additional command line options.
+    pub fn spawn_recovery_with_args(
+        &self,
+        backup: &Path,
+        sweep_address: Option<&Address>,
+        args: &[&str],
+    ) -> Recovery {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_hub-recovery"));
+        cmd.env("HUB_RECOVERY_SEED", HUB_SEED)
+            .arg("--non-interactive")
+            .args(["--data-dir", self.dir.join("recovery").to_str().unwrap()])
+            .args(["-b", backup.to_str().unwrap()])
+            .args(["-n", "regtest"])
+            .args(["--esplora-server", &self.esplora_url()])
+            .args(["--sync-interval-min", "1", "--sync-interval-max", "5"])
+            .stdout(Stdio::piped());
+        if let Some(address) = sweep_address {
+            cmd.args(["--sweep-address", &address.to_string()]);
+        }
+        cmd.args(args);
+
+        let mut child = cmd.spawn().unwrap();
+        let (tx, output) = mpsc::channel();
+        let stdout = child.stdout.take().unwrap();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                println!("hub-recovery: {}", line);
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Recovery { child, output }
+    }
+
+    /// Runs the report command of the recovery tool and returns the report.
+    pub fn report(&self, backup: &Path) -> serde_json::Value {
+        let output = Command::new(env!("CARGO_BIN_EXE_hub-recovery"))
+            .env("HUB_RECOVERY_SEED", HUB_SEED)
+            .arg("--non-interactive")
+            .args(["--data-dir", self.dir.join("recovery").to_str().unwrap()])
+            .args(["-b", backup.to_str().unwrap()])
+            .args(["-n", "regtest"])
+            .args(["--esplora-server", &self.esplora_url()])
+            .args(["report", "--format", "json"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "report failed: {}", output.status);
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    /// Runs the recovery tool to the end while mining a block every second,
+    /// so that closing transactions confirm and timelocks expire.
+    pub fn run_recovery(&self, backup: &Path, sweep_address: Option<&Address>) -> ExitStatus {
+        let mut recovery = self.spawn_recovery(backup, sweep_address);
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    self.mine(1);
+                    thread::sleep(Duration::from_secs(1));
+                }
+            });
+            let status = recovery.wait();
+            done.store(true, Ordering::Relaxed);
+            status
+        })
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A running instance of the recovery tool.
+pub struct Recovery {
+    child: Child,
+    output: mpsc::Receiver<String>,
+}
+
+impl Recovery {
+    /// Waits for a line of output containing `text`.
+    pub fn wait_for_output(&self, text: &str) {
+        let started = Instant::now();
+        loop {
+            let line = self
+                .output
+                .recv_timeout(TIMEOUT.saturating_sub(started.elapsed()))
+                .unwrap_or_else(|_| panic!("timed out waiting for output {:?}", text));
+            if line.contains(text) {
+                return;
+            }
+        }
+    }
+
+    /// Stops the tool the way a service manager would.
+    #[cfg(unix)]
+    pub fn terminate(&mut self) -> ExitStatus {
+        Command::new("kill")
+            .args(["-TERM", &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        self.wait()
+    }
+
+    /// Kills the tool without giving it a chance to clean up.
+    pub fn kill(&mut self) {
+        self.child.kill().unwrap();
+        self.child.wait().unwrap();
+    }
+
+    pub fn wait(&mut self) -> ExitStatus {
+        let started = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            if started.elapsed() > TIMEOUT {
+                self.kill();
+                panic!("recovery did not finish within {:?}", TIMEOUT);
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+pub fn wait_until(what: &str, mut condition: impl FnMut() -> bool) {
+    let started = Instant::now();
+    while !condition() {
+        assert!(
+            started.elapsed() < TIMEOUT,
+            "timed out waiting for {}",
+            what
+        );
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+pub fn wait_for_event(node: &Node, matches: impl Fn(&Event) -> bool) {
+    loop {
+        let event = node.wait_next_event();
+        node.event_handled().unwrap();
+        if matches(&event) {
+            return;
+        }
+    }
+}
+
+/// Writes a static channel backup of the node in the format exported by Alby
+/// Hub.
+pub fn write_backup(node: &Node, peer_address: &SocketAddress, path: &Path) {
+    let channels: Vec<Value> = node
+        .list_channels()
+        .iter()
+        .map(|c| {
+            json!({
+                "channel_id": hex::encode(c.channel_id.0),
+                "peer_id": c.counterparty_node_id.to_string(),
+                "peer_socket_address": peer_address.to_string(),
+                "funding_tx_id": c.funding_txo.unwrap().txid.to_string(),
+            })
+        })
+        .collect();
+    let monitors: Vec<Value> = node
+        .get_encoded_channel_monitors()
+        .unwrap()
+        .into_iter()
+        .map(|m| json!({ "key": m.key, "value": hex::encode(m.value) }))
+        .collect();
+
+    let backup = json!({
+        "node_id": node.node_id().to_string(),
+        "channels": channels,
+        "monitors": monitors,
+    });
+    fs::write(path, backup.to_string()).unwrap();
+}
+
+/// Rewrites a backup with its channel IDs changed, as if it had been taken
+/// from a different node.
+pub fn change_backup(path: &Path) {
+    let mut backup: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    for channel in backup["channels"].as_array_mut().unwrap() {
+        channel["channel_id"] = json!(hex::encode([0x42; 32]));
+    }
+    fs::write(path, backup.to_string()).unwrap();
+}
+
+/// Points the peer address of all channels in a backup at a closed port.
+pub fn break_peer_address(path: &Path) {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut backup: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    for channel in backup["channels"].as_array_mut().unwrap() {
+        channel["peer_socket_address"] = json!(format!("127.0.0.1:{}", port));
+    }
+    fs::write(path, backup.to_string()).unwrap();
+}
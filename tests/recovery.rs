@@ -0,0 +1,140 @@
+//! End-to-end tests of the recovery on regtest. They start bitcoind, electrs
+//! and a Lightning node acting as the hub's peer, so they are ignored by
+//! default; run them with `cargo test --test recovery -- --ignored`.
+
+mod common;
+
+use std::io::Read;
+use std::net::TcpListener;
+
+use common::TestEnv;
+
+const EXIT_IN_PROGRESS: i32 = 3;
+const EXIT_PEER_FAILURES: i32 = 4;
+const EXIT_BAD_BACKUP: i32 = 5;
+
+#[test]
+#[ignore = "starts bitcoind, electrs and a Lightning node"]
+fn test_recovery_sweeps_funds() {
+    let env = TestEnv::new("sweep");
+    let backup = env.dir.join("channel-backup.json");
+    let (_peer, sweep_address, onchain_balance) = env.setup_hub_with_channel(&backup);
+
+    let status = env.run_recovery(&backup, Some(&sweep_address));
+    assert!(status.success(), "recovery failed: {}", status);
+
+    // The channel held 500k sats, of which 100k were pushed to the peer. The
+    // change of the funding transaction is swept along with them.
+    let received = env.received(&sweep_address).saturating_sub(onchain_balance);
+    assert!(
+        received > 350_000 && received < 400_000,
+        "unexpected amount swept from the channel: {}",
+        received
+    );
+
+    let report = env.report(&backup);
+    let channel = &report[0];
+    assert!(
+        channel["closing_tx"].is_string(),
+        "no closing tx: {}",
+        report
+    );
+    assert!(!channel["sweep_txids"].as_array().unwrap().is_empty());
+    let recovered = channel["amount_recovered"].as_u64().unwrap();
+    let fees = channel["fees_paid"].as_u64().unwrap();
+    assert!(
+        channel["chain_error"].is_null(),
+        "lookup failed: {}",
+        report
+    );
+    // The report counts the swept outputs before the fees of the sweeps into
+    // the wallet. The sweep address received them after those fees and the
+    // fee of the final sweep.
+    assert!(
+        fees > 0 && recovered >= received + fees,
+        "unexpected report: {}",
+        report
+    );
+}
+
+#[test]
+#[ignore = "starts bitcoind, electrs and a Lightning node"]
+fn test_recovery_resumes_after_restart() {
+    let env = TestEnv::new("resume");
+    let backup = env.dir.join("channel-backup.json");
+    let (_peer, sweep_address, onchain_balance) = env.setup_hub_with_channel(&backup);
+
+    let mut recovery = env.spawn_recovery(&backup, None);
+    recovery.wait_for_output("Waiting for channel recovery to complete");
+    recovery.kill();
+
+    let status = env.run_recovery(&backup, Some(&sweep_address));
+    assert!(status.success(), "resumed recovery failed: {}", status);
+    let received = env.received(&sweep_address).saturating_sub(onchain_balance);
+    assert!(
+        received > 350_000,
+        "channel funds not swept after the restart: {}",
+        received
+    );
+}
+
+#[test]
+#[ignore = "starts bitcoind, electrs and a Lightning node"]
+fn test_recovery_syncs_through_proxy() {
+    let env = TestEnv::new("proxy");
+    let backup = env.dir.join("channel-backup.json");
+    env.setup_hub_with_channel(&backup);
+
+    // Stands in for the SOCKS5 proxy. With a single Esplora server the tool
+    // makes no requests of its own, so a connection comes from the node.
+    let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+    proxy.set_nonblocking(true).unwrap();
+    let proxy_url = format!("socks5://{}", proxy.local_addr().unwrap());
+    let mut recovery = env.spawn_recovery_with_args(&backup, None, &["--proxy", &proxy_url]);
+
+    let mut connection = None;
+    common::wait_until("a connection to the proxy", || {
+        connection = proxy.accept().ok();
+        connection.is_some()
+    });
+    let (mut stream, _) = connection.unwrap();
+    stream.set_nonblocking(false).unwrap();
+    let mut version = [0];
+    stream.read_exact(&mut version).unwrap();
+    assert_eq!(version[0], 5, "not a SOCKS5 handshake");
+    recovery.kill();
+}
+
+#[cfg(unix)]
+#[test]
+#[ignore = "starts bitcoind, electrs and a Lightning node"]
+fn test_recovery_reports_unreachable_peer() {
+    let env = TestEnv::new("peer");
+    let backup = env.dir.join("channel-backup.json");
+    env.setup_hub_with_channel(&backup);
+    common::break_peer_address(&backup);
+
+    let mut recovery = env.spawn_recovery(&backup, None);
+    recovery.wait_for_output("Failed to connect to the following peers");
+    recovery.wait_for_output("Waiting for channel recovery to complete");
+    let status = recovery.terminate();
+    assert_eq!(status.code(), Some(EXIT_PEER_FAILURES));
+}
+
+#[cfg(unix)]
+#[test]
+#[ignore = "starts bitcoind, electrs and a Lightning node"]
+fn test_recovery_rejects_changed_backup() {
+    let env = TestEnv::new("changed");
+    let backup = env.dir.join("channel-backup.json");
+    env.setup_hub_with_channel(&backup);
+
+    let mut recovery = env.spawn_recovery(&backup, None);
+    recovery.wait_for_output("Waiting for channel recovery to complete");
+    let status = recovery.terminate();
+    assert_eq!(status.code(), Some(EXIT_IN_PROGRESS));
+
+    common::change_backup(&backup);
+    let status = env.spawn_recovery(&backup, None).wait();
+    assert_eq!(status.code(), Some(EXIT_BAD_BACKUP));
+}
@@ -0,0 +1,59 @@
+//! Tests of the WebAssembly exports. They run under Node with
+//! `wasm-bindgen-test-runner`, see the README.
+#![cfg(target_arch = "wasm32")]
+
+use hub_recovery::wasm;
+use serde_json::{json, Value};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const SEED: &str = "limit reward expect search tissue call visa fit thank cream brave jump";
+const NODE_ID: &str = "037e702144c4fa485d42f0f69864e943605823763866cf4bf619d2d2cf2eda420b";
+const ENCRYPTED_BACKUP: &str = "3fd21f9a393d8345ddbdd449-ba05c3dbafdfb7eea574373b7763d0c81c599b2cd1735e59a1c5571379498f4da8fe834c3403824ab02b61005abc1f563c638f425c65420e82941efe94794555c8b145a0603733ee115277f860011e6a17fd8c22f1d73a096ff7275582aac19b430940b40a2559c7ff59a063305290ef7c9ba46f9de17b0ddbac9030b0";
+const OTHER_SEED: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+fn from_js(value: JsValue) -> Value {
+    serde_wasm_bindgen::from_value(value).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn test_derive_node_id() {
+    assert_eq!(wasm::derive_node_id(SEED).unwrap(), NODE_ID);
+    assert!(wasm::derive_node_id("not a seed phrase").is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_inspect_encrypted_backup() {
+    let summary = from_js(wasm::inspect_backup(ENCRYPTED_BACKUP, SEED).unwrap());
+    assert_eq!(
+        summary,
+        json!({ "node_id": NODE_ID, "channels": [], "monitors": [] })
+    );
+
+    assert!(wasm::inspect_backup(ENCRYPTED_BACKUP, OTHER_SEED).is_err());
+    assert!(wasm::inspect_backup(&ENCRYPTED_BACKUP[..40], SEED).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_parse_plain_backup() {
+    let backup = json!({
+        "node_id": NODE_ID,
+        "channels": [{
+            "channel_id": "aa",
+            "peer_id": "02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619",
+            "peer_socket_address": "127.0.0.1:9735",
+            "funding_tx_id": "bb",
+        }],
+        "monitors": [{ "key": "bb_0", "value": "00010000000000000005" }],
+    });
+
+    let summary = from_js(wasm::parse_backup(&backup.to_string()).unwrap());
+    assert_eq!(summary["channels"], backup["channels"]);
+    assert_eq!(
+        summary["monitors"],
+        json!([{ "key": "bb_0", "size": 10, "update_id": 5 }])
+    );
+
+    assert!(wasm::parse_backup("{\"channels\": []}").is_err());
+}